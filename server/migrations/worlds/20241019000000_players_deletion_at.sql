@@ -0,0 +1 @@
+ALTER TABLE players ADD COLUMN deletion_at BIGINT UNSIGNED NOT NULL DEFAULT 0;
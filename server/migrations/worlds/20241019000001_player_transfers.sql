@@ -0,0 +1,11 @@
+CREATE TABLE player_transfers (
+    id INT NOT NULL AUTO_INCREMENT,
+    player_id INT NOT NULL,
+    account_id INT NOT NULL,
+    from_world INT UNSIGNED NOT NULL,
+    to_world INT UNSIGNED NOT NULL,
+    price INT UNSIGNED NOT NULL DEFAULT 0,
+    date BIGINT UNSIGNED NOT NULL,
+    PRIMARY KEY (id),
+    KEY (player_id, date)
+);
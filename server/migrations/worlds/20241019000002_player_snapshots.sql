@@ -0,0 +1,20 @@
+CREATE TABLE player_snapshots (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    player_id INT NOT NULL,
+    world_id INT UNSIGNED NOT NULL,
+    time BIGINT UNSIGNED NOT NULL,
+    level INT UNSIGNED NOT NULL,
+    experience BIGINT UNSIGNED NOT NULL,
+    maglevel INT UNSIGNED NOT NULL,
+    manaspent BIGINT UNSIGNED NOT NULL,
+    skill_fist INT UNSIGNED NOT NULL,
+    skill_club INT UNSIGNED NOT NULL,
+    skill_sword INT UNSIGNED NOT NULL,
+    skill_axe INT UNSIGNED NOT NULL,
+    skill_dist INT UNSIGNED NOT NULL,
+    skill_shielding INT UNSIGNED NOT NULL,
+    skill_fishing INT UNSIGNED NOT NULL,
+    PRIMARY KEY (id),
+    KEY (player_id, time),
+    KEY (time)
+);
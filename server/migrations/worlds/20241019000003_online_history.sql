@@ -0,0 +1,10 @@
+CREATE TABLE online_history (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    world_id INT UNSIGNED NOT NULL,
+    time BIGINT UNSIGNED NOT NULL,
+    count INT UNSIGNED NOT NULL,
+    PRIMARY KEY (id),
+    KEY (world_id, time),
+    KEY (world_id, count),
+    KEY (time)
+);
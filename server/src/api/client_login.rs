@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use poem::{
+    handler,
+    web::{Data, Json},
+    IntoResponse, Response, Result,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, PvpType},
+    services::{
+        databases::Databases,
+        repository::{self, Credential},
+    },
+    utils::time,
+};
+
+/// Login endpoint of the Tibia 11+ client, see `login.path`
+#[handler]
+pub async fn login(
+    Json(request): Json<Request>,
+    Data(databases): Data<&Arc<Databases>>,
+) -> Result<Response> {
+    let cfg = config::get();
+    Ok(match request {
+        Request::Login(data) => session(databases, data).await?,
+        Request::CacheInfo => {
+            let mut online = 0;
+            for &world in cfg.worlds.keys() {
+                online += repository::online_count(databases.world(world)?, world).await?;
+            }
+            Json(CacheInfo {
+                playersonline: online,
+                twitchstreams: 0,
+                twitchviewer: 0,
+                gamingyoutubestreams: 0,
+                gamingyoutubeviewer: 0,
+            })
+            .into_response()
+        }
+        Request::EventSchedule => Json(EventSchedule {
+            eventlist: cfg
+                .login
+                .events
+                .iter()
+                .map(|e| Event {
+                    name: e.name.clone(),
+                    description: e.description.clone(),
+                    startdate: e.start,
+                    enddate: e.end,
+                    colorlight: e.color_light.clone(),
+                    colordark: e.color_dark.clone(),
+                    isseasonal: false,
+                    specialevent: 0,
+                })
+                .collect(),
+            lastupdatetimestamp: time::now() as u64,
+        })
+        .into_response(),
+        Request::BoostedCreature => Json(BoostedCreature {
+            boostedcreature: cfg.login.boosted_creature != 0,
+            raceid: cfg.login.boosted_creature,
+        })
+        .into_response(),
+    })
+}
+
+async fn session(databases: &Databases, data: LoginRequest) -> Result<Response> {
+    let credential = match (&data.email, &data.accountname) {
+        (Some(email), _) => Credential::Email(email),
+        (None, Some(name)) => Credential::Name(name),
+        (None, None) => return Ok(error(3, "Account name or password is not correct.")),
+    };
+    let Some(account) =
+        repository::account(databases.accounts(), credential, &data.password).await?
+    else {
+        return Ok(error(3, "Account name or password is not correct."));
+    };
+
+    let cfg = config::get();
+    let characters = repository::characters(databases, account.id)
+        .await?
+        .into_iter()
+        .filter(|c| cfg.worlds.contains_key(&c.world_id))
+        .map(|c| Character {
+            worldid: c.world_id,
+            name: c.name,
+            ismale: c.sex == 1,
+            tutorial: false,
+            level: c.level,
+            vocation: cfg.vocation_name(c.vocation).unwrap_or("None").to_owned(),
+            outfitid: c.looktype,
+            headcolor: c.lookhead,
+            torsocolor: c.lookbody,
+            legscolor: c.looklegs,
+            detailcolor: c.lookfeet,
+            addonsflags: c.lookaddons,
+            ishidden: false,
+            istournamentparticipant: false,
+            ismaincharacter: false,
+            dailyrewardstate: 0,
+            remainingdailytournamentplaytime: 0,
+        })
+        .collect();
+
+    let mut worlds = cfg
+        .worlds
+        .iter()
+        .map(|(&id, w)| World {
+            id,
+            name: w.name.clone(),
+            externaladdress: w.ip.to_string(),
+            externalport: w.port,
+            externaladdressprotected: w.ip.to_string(),
+            externalportprotected: w.port,
+            externaladdressunprotected: w.ip.to_string(),
+            externalportunprotected: w.port,
+            previewstate: 0,
+            location: w.location.clone(),
+            anticheatprotection: false,
+            pvptype: match w.pvp_type {
+                PvpType::Pvp => 0,
+                PvpType::NoPvp => 1,
+                PvpType::PvpEnforced => 2,
+            },
+            istournamentworld: false,
+            restrictedstore: false,
+            currenttournamentphase: 2,
+        })
+        .collect::<Vec<_>>();
+    worlds.sort_by_key(|w| w.id);
+
+    let premium = account.premdays > 0;
+    Ok(Json(LoginResponse {
+        session: Session {
+            sessionkey: session_key(&account.name, &data.password),
+            lastlogintime: 0,
+            ispremium: premium,
+            premiumuntil: if premium {
+                time::now() as u64 + account.premdays as u64 * 24 * 60 * 60
+            } else {
+                0
+            },
+            status: "active",
+            returnernotification: false,
+            showrewardnews: false,
+            isreturner: false,
+            fpstracking: false,
+            optiontracking: false,
+            tournamentticketpurchasestate: 0,
+            emailcoderequest: false,
+        },
+        playdata: PlayData { worlds, characters },
+    })
+    .into_response())
+}
+
+/// Game servers without `account_sessions`, TFS 0.4 included, split the key at the
+/// newline and check it like a classic account login
+fn session_key(account: &str, password: &str) -> String {
+    format!("{account}\n{password}")
+}
+
+fn error(code: u32, message: &'static str) -> Response {
+    Json(Error {
+        error_code: code,
+        error_message: message,
+    })
+    .into_response()
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Request {
+    Login(LoginRequest),
+    CacheInfo,
+    EventSchedule,
+    BoostedCreature,
+}
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    email: Option<String>,
+    accountname: Option<String>,
+    password: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Error {
+    error_code: u32,
+    error_message: &'static str,
+}
+
+#[derive(Serialize)]
+struct LoginResponse {
+    session: Session,
+    playdata: PlayData,
+}
+
+#[derive(Serialize)]
+struct Session {
+    sessionkey: String,
+    lastlogintime: u64,
+    ispremium: bool,
+    premiumuntil: u64,
+    status: &'static str,
+    returnernotification: bool,
+    showrewardnews: bool,
+    isreturner: bool,
+    fpstracking: bool,
+    optiontracking: bool,
+    tournamentticketpurchasestate: u32,
+    emailcoderequest: bool,
+}
+
+#[derive(Serialize)]
+struct PlayData {
+    worlds: Vec<World>,
+    characters: Vec<Character>,
+}
+
+#[derive(Serialize)]
+struct World {
+    id: u32,
+    name: String,
+    externaladdress: String,
+    externalport: u16,
+    externaladdressprotected: String,
+    externalportprotected: u16,
+    externaladdressunprotected: String,
+    externalportunprotected: u16,
+    previewstate: u32,
+    location: String,
+    anticheatprotection: bool,
+    pvptype: u32,
+    istournamentworld: bool,
+    restrictedstore: bool,
+    currenttournamentphase: u32,
+}
+
+#[derive(Serialize)]
+struct Character {
+    worldid: u32,
+    name: String,
+    ismale: bool,
+    tutorial: bool,
+    level: u32,
+    vocation: String,
+    outfitid: u32,
+    headcolor: u32,
+    torsocolor: u32,
+    legscolor: u32,
+    detailcolor: u32,
+    addonsflags: u32,
+    ishidden: bool,
+    istournamentparticipant: bool,
+    ismaincharacter: bool,
+    dailyrewardstate: u32,
+    remainingdailytournamentplaytime: u32,
+}
+
+#[derive(Serialize)]
+struct CacheInfo {
+    playersonline: u32,
+    twitchstreams: u32,
+    twitchviewer: u32,
+    gamingyoutubestreams: u32,
+    gamingyoutubeviewer: u32,
+}
+
+#[derive(Serialize)]
+struct EventSchedule {
+    eventlist: Vec<Event>,
+    lastupdatetimestamp: u64,
+}
+
+#[derive(Serialize)]
+struct Event {
+    name: String,
+    description: String,
+    startdate: u64,
+    enddate: u64,
+    colorlight: String,
+    colordark: String,
+    isseasonal: bool,
+    specialevent: u32,
+}
+
+#[derive(Serialize)]
+struct BoostedCreature {
+    boostedcreature: bool,
+    raceid: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::Method, Endpoint, EndpointExt};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    async fn post(body: Value) -> Value {
+        let endpoint = login.data(Arc::new(Databases::unconnected()));
+        let request = poem::Request::builder()
+            .method(Method::POST)
+            .content_type("application/json")
+            .body(body.to_string());
+        endpoint
+            .get_response(request)
+            .await
+            .into_body()
+            .into_json()
+            .await
+            .expect("json")
+    }
+
+    #[tokio::test]
+    async fn login_without_account_is_rejected() {
+        let response = post(json!({ "type": "login", "password": "secret" })).await;
+        assert_eq!(response["errorCode"], 3);
+    }
+
+    #[tokio::test]
+    async fn cacheinfo_counts_configured_worlds() {
+        let response = post(json!({ "type": "cacheinfo" })).await;
+        assert_eq!(response["playersonline"], 0);
+        assert_eq!(response["twitchstreams"], 0);
+    }
+
+    #[tokio::test]
+    async fn boosted_creature_defaults_to_none() {
+        let response = post(json!({ "type": "boostedcreature" })).await;
+        assert_eq!(response["boostedcreature"], false);
+        assert_eq!(response["raceid"], 0);
+    }
+
+    #[tokio::test]
+    async fn event_schedule_lists_configured_events() {
+        let response = post(json!({ "type": "eventschedule" })).await;
+        assert_eq!(response["eventlist"], json!([]));
+    }
+
+    #[test]
+    fn session_key_carries_the_classic_credentials() {
+        assert_eq!(session_key("1234567", "secret"), "1234567\nsecret");
+    }
+}
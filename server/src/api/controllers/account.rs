@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use crate::{
     api::jwt_bearer::{JwtAccountId, JwtRefreshId},
-    services::jwt,
+    services::{
+        databases::Databases,
+        jwt,
+        repository::{self, Credential},
+    },
     utils::time,
 };
 
@@ -14,11 +18,11 @@ use poem_openapi::{payload::Json, Object, OpenApi};
 use sqlx::{query, query_as, FromRow, MySql, Pool};
 
 pub struct Api {
-    db: Pool<MySql>,
+    db: Arc<Databases>,
     jwt: Arc<jwt::Service>,
 }
 
-pub fn api(db: &Pool<MySql>, jwt: &Arc<jwt::Service>) -> Api {
+pub fn api(db: &Arc<Databases>, jwt: &Arc<jwt::Service>) -> Api {
     Api {
         db: db.clone(),
         jwt: jwt.clone(),
@@ -36,7 +40,7 @@ impl Api {
             &data.account,
             &data.email
         )
-        .fetch_optional(&self.db)
+        .fetch_optional(self.db.accounts())
         .await
         .context("validation")?
         {
@@ -56,7 +60,7 @@ impl Api {
             &data.email,
             time::now() as i64
         )
-        .execute(&self.db)
+        .execute(self.db.accounts())
         .await
         .context("account insert")?
         .last_insert_id() as i32;
@@ -72,7 +76,7 @@ impl Api {
     /// Generate login tokens
     #[oai(path = "/login", method = "post")]
     async fn login(&self, data: Json<Login>) -> Result<Json<Tokens>> {
-        let id = account_id(&data, &self.db).await?;
+        let id = account_id(&data, self.db.accounts()).await?;
         let (account_token, refresh_token) = self.jwt.register(id)?;
         Ok(Json(Tokens {
             account_token,
@@ -101,18 +105,23 @@ impl Api {
     #[oai(path = "/", method = "get")]
     async fn account(&self, auth: JwtAccountId) -> Result<Json<Account>> {
         let premium_points = query!("SELECT premium_points FROM accounts WHERE id=?", &auth.0)
-            .fetch_one(&self.db)
+            .fetch_one(self.db.accounts())
             .await
             .context("nindo")?
             .premium_points;
-        let characters = query_as!(
-            AccountCharacter,
-            r#"SELECT id, name, level, deleted AS "deleted:_" FROM players WHERE account_id=?"#,
-            &auth.0
-        )
-        .fetch_all(&self.db)
-        .await
-        .context("players")?;
+        let mut characters = Vec::new();
+        for db in self.db.all() {
+            let worlds = self.db.worlds_in(db);
+            let found = query_as!(
+                AccountCharacter,
+                r#"SELECT world_id AS "world: u32", id, name, level, deleted AS "deleted:_", NULLIF(deletion_at, 0) AS "deletion_at?: u64" FROM players WHERE account_id=?"#,
+                &auth.0
+            )
+            .fetch_all(db)
+            .await
+            .context("players")?;
+            characters.extend(found.into_iter().filter(|c| worlds.contains(&c.world)));
+        }
         Ok(Json(Account {
             characters,
             premium_points,
@@ -131,7 +140,7 @@ impl Api {
             &auth.0,
             &data.current
         )
-        .execute(&self.db)
+        .execute(self.db.accounts())
         .await
         .context("change password")?
         .rows_affected()
@@ -187,22 +196,20 @@ struct Account {
 }
 
 #[derive(Object, FromRow)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
 struct AccountCharacter {
+    /// Characters are referenced by world and id
+    world: u32,
     id: i32,
     name: String,
     level: u32,
     deleted: bool,
+    deletion_at: Option<u64>,
 }
 
 async fn account_id(data: &Login, db: &Pool<MySql>) -> Result<i32> {
-    query!(
-        "SELECT id FROM accounts WHERE BINARY name=? AND BINARY password=?",
-        &data.account,
-        &data.password
-    )
-    .fetch_optional(db)
-    .await
-    .context("aid")?
-    .map(|r| r.id)
-    .ok_or(StatusCode::UNAUTHORIZED.into())
+    repository::account(db, Credential::Name(&data.account), &data.password)
+        .await?
+        .map(|a| a.id)
+        .ok_or(StatusCode::UNAUTHORIZED.into())
 }
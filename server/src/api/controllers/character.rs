@@ -1,18 +1,31 @@
-use crate::{api::jwt_bearer::JwtAccountId, config};
+use std::sync::Arc;
 
-use super::prelude::*;
+use crate::{
+    api::{jwt_bearer::JwtAccountId, name_policy::NamePolicy},
+    config::{self, ContainerItem, ItemTemplate},
+    services::{cache::Cache, databases::Databases, deletion},
+    utils::{experience, time},
+};
+
+use super::{guard, prelude::*};
 use anyhow::Context;
 use delirium_macros::Validation;
 use poem_openapi::{payload::Json, Object, OpenApi};
-use sqlx::{query, query_as, FromRow, MySql, Pool};
-use tracing::info;
+use sqlx::{query, query_as, FromRow, MySql, Pool, Transaction};
+use tracing::{error, info};
 
 pub struct Api {
-    db: Pool<MySql>,
+    db: Arc<Databases>,
+    names: Arc<NamePolicy>,
+    cache: Arc<Cache>,
 }
 
-pub fn api(db: &Pool<MySql>) -> Api {
-    Api { db: db.clone() }
+pub fn api(db: &Arc<Databases>, names: &Arc<NamePolicy>, cache: &Arc<Cache>) -> Api {
+    Api {
+        db: db.clone(),
+        names: names.clone(),
+        cache: cache.clone(),
+    }
 }
 
 #[OpenApi(prefix_path = "/character", tag = "super::Tags::Character")]
@@ -25,88 +38,141 @@ impl Api {
         mut data: Json<CreateCharacter>,
     ) -> Result<Json<i32>> {
         data.validate()?;
+        self.names.check(&data.name)?;
         let cfg = config::get();
-        if !cfg.worlds.contains_key(&data.world) {
+        let Some(new) = cfg.new_character(data.world) else {
             return Err(InvalidData.into());
-        }
-        let Some(voc) = cfg.character.new.vocations.get(&data.vocation) else {
+        };
+        let Some(voc) = new.vocations.get(&data.vocation) else {
             return Err(InvalidData.into());
         };
+        let target = world(data.world)?;
+        if !target.visible {
+            return Err(InvalidData.into());
+        }
+        if target.premium_only && !premium(self.db.accounts(), auth.0).await? {
+            return Err(PremiumWorld.into());
+        }
 
-        if query!(
-            "SELECT COUNT(*) count FROM players WHERE account_id = ?",
-            &auth.0,
-        )
-        .fetch_optional(&self.db)
-        .await
-        .context("validation")?
-        .is_some_and(|x| x.count >= cfg.account.max_characters as i64)
-        {
+        let mut count = 0;
+        for db in self.db.all() {
+            count += query!(
+                "SELECT COUNT(*) count FROM players WHERE account_id = ?",
+                &auth.0,
+            )
+            .fetch_one(db)
+            .await
+            .context("validation")?
+            .count;
+        }
+        if count >= cfg.account.max_characters as i64 {
             return Err(TooManyCharacters.into());
         }
 
-        if query!(
-            "SELECT name FROM players WHERE name LIKE ? LIMIT 1",
-            &data.name,
-        )
-        .fetch_optional(&self.db)
-        .await
-        .context("validation")?
-        .is_some()
-        {
-            return Err(CharacterAlreadyExists.into());
-        }
-
-        let cfg = &cfg.character.new;
-        let id = query!("INSERT INTO players (name, world_id, account_id, vocation, health, healthmax, looktype, mana, manamax, soul, town_id, posx, posy, posz, cap) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-        &data.name,
-        &data.world,
-        &auth.0,
-        voc.vocation,
-        cfg.health,
-        cfg.health,
-        voc.looktype,
-        cfg.mana,
-        cfg.mana,
-        cfg.soul,
-        cfg.town,
-        cfg.pos_x,
-        cfg.pos_y,
-        cfg.pos_z,
-        cfg.cap)
-            .execute(&self.db)
+        for db in self.db.all() {
+            if query!(
+                "SELECT name FROM players WHERE name LIKE ? LIMIT 1",
+                &data.name,
+            )
+            .fetch_optional(db)
+            .await
+            .context("validation")?
+            .is_some()
+            {
+                return Err(CharacterAlreadyExists.into());
+            }
+        }
+
+        let mut tx = self
+            .db
+            .world(data.world)?
+            .begin()
             .await
-            .context("player insert")?
-            .last_insert_id() as i32;
+            .context("transaction")?;
+        let id = if let Some(sample) = voc.sample(data.world) {
+            clone_sample(&mut tx, sample, &data.name, data.world, auth.0).await?
+        } else {
+            let id = query!("INSERT INTO players (name, world_id, account_id, vocation, level, experience, health, healthmax, looktype, mana, manamax, soul, town_id, posx, posy, posz, cap) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            &data.name,
+            &data.world,
+            &auth.0,
+            voc.vocation,
+            new.level,
+            experience::for_level(new.level).context("experience")?,
+            new.health,
+            new.health,
+            voc.looktype,
+            new.mana,
+            new.mana,
+            new.soul,
+            new.town,
+            new.pos_x,
+            new.pos_y,
+            new.pos_z,
+            new.cap)
+                .execute(&mut *tx)
+                .await
+                .context("player insert")?
+                .last_insert_id() as i32;
+            for item in item_rows(&voc.items) {
+                query!(
+                    "INSERT INTO player_items (player_id, pid, sid, itemtype, count, attributes) VALUES (?, ?, ?, ?, ?, '')",
+                    id,
+                    item.pid,
+                    item.sid,
+                    item.itemtype,
+                    item.count
+                )
+                .execute(&mut *tx)
+                .await
+                .context("player items insert")?;
+            }
+            id
+        };
+        tx.commit().await.context("commit")?;
+        self.cache.invalidate_characters();
         Ok(Json(id))
     }
 
     /// Delete Character
     #[oai(path = "/", method = "delete")]
-    async fn delete(&self, auth: JwtAccountId, id: Json<i32>) -> Result<()> {
+    async fn delete(&self, auth: JwtAccountId, character: Json<CharacterRef>) -> Result<()> {
+        world(character.world)?;
+        let db = self.db.world(character.world)?;
+        let mut tx = db.begin().await.context("transaction")?;
         let record = query!(
-            "SELECT id, account_id, level FROM players WHERE id=? AND NOT deleted",
-            id.0
+            "SELECT id, account_id, level FROM players WHERE id=? AND world_id=? AND NOT deleted FOR UPDATE",
+            character.id,
+            character.world
         )
-        .fetch_optional(&self.db)
+        .fetch_optional(&mut *tx)
         .await
         .context("record")?;
         if let Some(record) = record {
             if record.account_id == auth.0 {
+                guard::character_offline(&mut tx, record.id).await?;
                 let cfg = config::get();
                 if record.level < cfg.character.insta_delete_below {
-                    query!("DELETE FROM players WHERE id=?", record.id)
-                        .execute(&self.db)
-                        .await
-                        .context("delete player")?;
-                    info!("Deleted character '{}'", id.0);
+                    deletion::purge(&mut tx, record.id).await?;
+                    tx.commit().await.context("commit")?;
+                    info!("Deleted character '{}'", record.id);
                 } else {
-                    query!("UPDATE players SET deleted=1 WHERE id=?", record.id)
-                        .execute(&self.db)
-                        .await
-                        .context("mark delete player")?;
-                    info!("Marked character '{}' as deleted", id.0);
+                    let deletion_at = time::timestamp() + cfg.character.deletion_time;
+                    query!(
+                        "UPDATE players SET deleted=1, deletion_at=? WHERE id=?",
+                        deletion_at,
+                        record.id
+                    )
+                    .execute(&mut *tx)
+                    .await
+                    .context("mark delete player")?;
+                    tx.commit().await.context("commit")?;
+                    info!(
+                        "Scheduled character '{}' deletion at {}",
+                        record.id, deletion_at
+                    );
                 }
+                self.cache.invalidate_characters();
             }
         };
         Ok(())
@@ -114,35 +180,192 @@ impl Api {
 
     /// Undelete Character
     #[oai(path = "/", method = "patch")]
-    async fn undelete(&self, auth: JwtAccountId, id: Json<i32>) -> Result<()> {
+    async fn undelete(&self, auth: JwtAccountId, character: Json<CharacterRef>) -> Result<()> {
+        world(character.world)?;
+        let db = self.db.world(character.world)?;
+        let mut tx = db.begin().await.context("transaction")?;
         let record = query!(
-            "SELECT id, account_id, level FROM players WHERE id=? AND deleted",
-            id.0
+            "SELECT id, account_id FROM players WHERE id=? AND world_id=? AND deleted AND (deletion_at = 0 OR deletion_at > ?) FOR UPDATE",
+            character.id,
+            character.world,
+            time::timestamp()
         )
-        .fetch_optional(&self.db)
+        .fetch_optional(&mut *tx)
         .await
         .context("record")?;
         if let Some(record) = record {
             if record.account_id == auth.0 {
-                query!("UPDATE players SET deleted=0 WHERE id=?", record.id)
-                    .execute(&self.db)
-                    .await
-                    .context("mark undelete player")?;
-                info!("Undeleted character '{}'", id.0);
+                guard::character_offline(&mut tx, record.id).await?;
+                query!(
+                    "UPDATE players SET deleted=0, deletion_at=0 WHERE id=?",
+                    record.id
+                )
+                .execute(&mut *tx)
+                .await
+                .context("mark undelete player")?;
+                tx.commit().await.context("commit")?;
+                info!("Undeleted character '{}'", record.id);
+                self.cache.invalidate_characters();
             }
         };
         Ok(())
     }
 
+    /// Transfer Character to another world
+    #[oai(path = "/transfer", method = "post")]
+    async fn transfer(&self, auth: JwtAccountId, data: Json<TransferCharacter>) -> Result<()> {
+        let cfg = config::get();
+        let transfer = &cfg.character.transfer;
+        if !transfer.enabled {
+            return Err(TransferDisabled.into());
+        }
+        world(data.world)?;
+        let target = world(data.target)?;
+        if !target.visible {
+            return Err(InvalidData.into());
+        }
+        // Characters are not copied between databases
+        if data.world == data.target || !self.db.shared(data.world, data.target) {
+            return Err(InvalidData.into());
+        }
+        if target.premium_only && !premium(self.db.accounts(), auth.0).await? {
+            return Err(PremiumWorld.into());
+        }
+        let db = self.db.world(data.world)?;
+        let mut tx = db.begin().await.context("transaction")?;
+        let record = query!(
+            "SELECT id, account_id, name, level, vocation, rank_id FROM players WHERE id=? AND world_id=? AND NOT deleted FOR UPDATE",
+            data.id,
+            data.world
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .context("record")?
+        .filter(|r| r.account_id == auth.0)
+        .ok_or(CharacterNotExists)?;
+        guard::character_offline(&mut tx, record.id).await?;
+
+        // The target world shares the database, so its names are checked in the same transaction
+        if query!(
+            "SELECT id FROM players WHERE name=? AND world_id=? AND id<>? LIMIT 1 FOR UPDATE",
+            record.name,
+            data.target,
+            record.id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .context("name clash")?
+        .is_some()
+        {
+            return Err(TransferNameTaken.into());
+        }
+        if !cfg.vocation_allowed(data.target, record.vocation) {
+            return Err(TransferVocation.into());
+        }
+        if record.level < transfer.min_level
+            || (transfer.max_level > 0 && record.level > transfer.max_level)
+        {
+            return Err(TransferLevel {
+                min: transfer.min_level,
+                max: transfer.max_level,
+            }
+            .into());
+        }
+        if !transfer.allow_guild && record.rank_id > 0 {
+            return Err(CharacterInGuild.into());
+        }
+        if !transfer.allow_house
+            && query!(
+                "SELECT id FROM houses WHERE owner=? LIMIT 1 FOR UPDATE",
+                record.id
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .context("house")?
+            .is_some()
+        {
+            return Err(CharacterHasHouse.into());
+        }
+        if transfer.cooldown > 0 {
+            let last = query!(
+                r#"SELECT MAX(date) AS "date: u64" FROM player_transfers WHERE player_id=? FOR UPDATE"#,
+                record.id
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .context("last transfer")?
+            .date;
+            if let Some(until) = last.map(|date| date + transfer.cooldown as u64) {
+                if until > time::timestamp() {
+                    return Err(TransferCooldown { until }.into());
+                }
+            }
+        }
+
+        // The accounts database commits on its own, the character is locked meanwhile
+        // and the points are given back if moving it fails
+        if transfer.price > 0
+            && query!(
+                "UPDATE accounts SET premium_points = premium_points - ? WHERE id=? AND premium_points >= ?",
+                transfer.price,
+                &auth.0,
+                transfer.price
+            )
+            .execute(self.db.accounts())
+            .await
+            .context("transfer payment")?
+            .rows_affected()
+                == 0
+        {
+            return Err(NotEnoughPremiumPoints.into());
+        }
+        if let Err(err) = move_character(
+            tx,
+            record.id,
+            auth.0,
+            data.world,
+            data.target,
+            transfer.price,
+        )
+        .await
+        {
+            if transfer.price > 0 {
+                if let Err(refund) = query!(
+                    "UPDATE accounts SET premium_points = premium_points + ? WHERE id=?",
+                    transfer.price,
+                    &auth.0
+                )
+                .execute(self.db.accounts())
+                .await
+                {
+                    error!(
+                        "Refunding {} points of the failed transfer of character '{}' to account {} failed: {:?}",
+                        transfer.price, record.id, auth.0, refund
+                    );
+                }
+            }
+            return Err(err.into());
+        }
+        self.cache.invalidate_characters();
+        info!(
+            "Transferred character '{}' from world {} to {}",
+            record.id, data.world, data.target
+        );
+        Ok(())
+    }
+
     /// Get Character
     #[oai(path = "/", method = "get")]
-    async fn character(&self, id: Json<i32>) -> Result<Json<Character>> {
+    async fn character(&self, character: Json<CharacterRef>) -> Result<Json<Character>> {
+        world(character.world)?;
+        let db = self.db.world(character.world)?;
         let record = query_as!(
             CharacterRow,
-            "SELECT name, level, vocation, world_id FROM players WHERE id=? AND NOT deleted",
-            id.0
+            "SELECT name, level, vocation, world_id FROM players WHERE id=? AND world_id=? AND NOT deleted",
+            character.id,
+            character.world
         )
-        .fetch_optional(&self.db)
+        .fetch_optional(db)
         .await
         .context("record")?
         .ok_or(CharacterNotExists)?;
@@ -157,13 +380,11 @@ impl Api {
                 }
             }
         }
-        let mut world = String::new();
-        for (id, name) in cfg.worlds.iter() {
-            if &record.world_id == id {
-                world = name.clone();
-                break;
-            }
-        }
+        let world = cfg
+            .worlds
+            .get(&record.world_id)
+            .map(|w| w.name.clone())
+            .unwrap_or_default();
         Ok(Json(Character {
             name: record.name,
             level: record.level,
@@ -182,6 +403,141 @@ struct CreateCharacter {
     world: u32,
 }
 
+#[derive(Object)]
+struct TransferCharacter {
+    /// Current world of the character
+    world: u32,
+    id: i32,
+    /// World to move it to
+    target: u32,
+}
+
+async fn premium(db: &Pool<MySql>, account_id: i32) -> anyhow::Result<bool> {
+    Ok(
+        query!("SELECT premdays FROM accounts WHERE id=?", account_id)
+            .fetch_one(db)
+            .await
+            .context("premium")?
+            .premdays
+            > 0,
+    )
+}
+
+async fn move_character(
+    mut tx: Transaction<'_, MySql>,
+    id: i32,
+    account_id: i32,
+    from: u32,
+    to: u32,
+    price: u32,
+) -> anyhow::Result<()> {
+    query!("UPDATE players SET world_id=? WHERE id=?", to, id)
+        .execute(&mut *tx)
+        .await
+        .context("transfer player")?;
+    query!(
+        "INSERT INTO player_transfers (player_id, account_id, from_world, to_world, price, date) VALUES (?, ?, ?, ?, ?, ?)",
+        id,
+        account_id,
+        from,
+        to,
+        price,
+        time::timestamp()
+    )
+    .execute(&mut *tx)
+    .await
+    .context("transfer history")?;
+    tx.commit().await.context("commit")
+}
+
+async fn clone_sample(
+    tx: &mut Transaction<'_, MySql>,
+    sample: i32,
+    name: &str,
+    world: u32,
+    account_id: i32,
+) -> Result<i32> {
+    let result = query!(
+        "INSERT INTO players (name, world_id, account_id, vocation, sex, level, experience, health, healthmax, mana, manamax, maglevel, manaspent, soul, cap, looktype, lookhead, lookbody, looklegs, lookfeet, lookaddons, town_id, posx, posy, posz, skill_fist, skill_fist_tries, skill_club, skill_club_tries, skill_sword, skill_sword_tries, skill_axe, skill_axe_tries, skill_dist, skill_dist_tries, skill_shielding, skill_shielding_tries, skill_fishing, skill_fishing_tries) SELECT ?, ?, ?, vocation, sex, level, experience, health, healthmax, mana, manamax, maglevel, manaspent, soul, cap, looktype, lookhead, lookbody, looklegs, lookfeet, lookaddons, town_id, posx, posy, posz, skill_fist, skill_fist_tries, skill_club, skill_club_tries, skill_sword, skill_sword_tries, skill_axe, skill_axe_tries, skill_dist, skill_dist_tries, skill_shielding, skill_shielding_tries, skill_fishing, skill_fishing_tries FROM players WHERE id=?",
+        name,
+        world,
+        account_id,
+        sample
+    )
+    .execute(&mut **tx)
+    .await
+    .context("sample player insert")?;
+    if result.rows_affected() == 0 {
+        return Err(SampleNotExists.into());
+    }
+    let id = result.last_insert_id() as i32;
+
+    query!(
+        "INSERT INTO player_items (player_id, pid, sid, itemtype, count, attributes) SELECT ?, pid, sid, itemtype, count, attributes FROM player_items WHERE player_id=?",
+        id,
+        sample
+    )
+    .execute(&mut **tx)
+    .await
+    .context("sample items")?;
+    query!(
+        "INSERT INTO player_storage (player_id, `key`, value) SELECT ?, `key`, value FROM player_storage WHERE player_id=?",
+        id,
+        sample
+    )
+    .execute(&mut **tx)
+    .await
+    .context("sample storage")?;
+    query!(
+        "INSERT INTO player_spells (player_id, name) SELECT ?, name FROM player_spells WHERE player_id=?",
+        id,
+        sample
+    )
+    .execute(&mut **tx)
+    .await
+    .context("sample spells")?;
+    Ok(id)
+}
+
+struct ItemRow {
+    pid: u32,
+    sid: u32,
+    itemtype: u32,
+    count: u32,
+}
+
+// inventory items are keyed by slot, container contents by their parent's sid
+fn item_rows(items: &[ItemTemplate]) -> Vec<ItemRow> {
+    let mut rows = Vec::new();
+    let mut sid = 100;
+    for item in items {
+        sid += 1;
+        let parent = sid;
+        rows.push(ItemRow {
+            pid: item.slot,
+            sid,
+            itemtype: item.item,
+            count: item.count,
+        });
+        content_rows(&mut rows, &mut sid, parent, &item.contents);
+    }
+    rows
+}
+
+fn content_rows(rows: &mut Vec<ItemRow>, sid: &mut u32, pid: u32, contents: &[ContainerItem]) {
+    for item in contents {
+        *sid += 1;
+        let parent = *sid;
+        rows.push(ItemRow {
+            pid,
+            sid: parent,
+            itemtype: item.item,
+            count: item.count,
+        });
+        content_rows(rows, sid, parent, &item.contents);
+    }
+}
+
 #[derive(FromRow)]
 struct CharacterRow {
     name: String,
@@ -198,3 +554,78 @@ struct Character {
     vocation: String,
     world: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(items: &[ItemTemplate]) -> Vec<(u32, u32, u32, u32)> {
+        item_rows(items)
+            .into_iter()
+            .map(|r| (r.pid, r.sid, r.itemtype, r.count))
+            .collect()
+    }
+
+    fn content(item: u32, count: u32, contents: Vec<ContainerItem>) -> ContainerItem {
+        ContainerItem {
+            item,
+            count,
+            contents,
+        }
+    }
+
+    #[test]
+    fn inventory_items_are_keyed_by_slot() {
+        let items = [
+            ItemTemplate {
+                slot: 1,
+                item: 2460,
+                count: 1,
+                contents: Vec::new(),
+            },
+            ItemTemplate {
+                slot: 10,
+                item: 2544,
+                count: 100,
+                contents: Vec::new(),
+            },
+        ];
+        assert_eq!(rows(&items), [(1, 101, 2460, 1), (10, 102, 2544, 100)]);
+    }
+
+    #[test]
+    fn nested_containers_point_to_their_parent() {
+        let items = [
+            ItemTemplate {
+                slot: 3,
+                item: 1988,
+                count: 1,
+                contents: vec![
+                    content(1987, 1, vec![content(2148, 5, Vec::new())]),
+                    content(2120, 1, Vec::new()),
+                ],
+            },
+            ItemTemplate {
+                slot: 5,
+                item: 2512,
+                count: 1,
+                contents: Vec::new(),
+            },
+        ];
+        assert_eq!(
+            rows(&items),
+            [
+                (3, 101, 1988, 1),
+                (101, 102, 1987, 1),
+                (102, 103, 2148, 5),
+                (101, 104, 2120, 1),
+                (5, 105, 2512, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_items_no_rows() {
+        assert!(rows(&[]).is_empty());
+    }
+}
@@ -1,16 +1,28 @@
-use crate::config;
+use std::sync::Arc;
+
+use crate::{
+    config,
+    services::{
+        cache::Cache,
+        databases::Databases,
+        repository::{self, DeathFilter},
+    },
+};
 
 use super::prelude::*;
 use anyhow::Context;
 use poem_openapi::{payload::Json, Object, OpenApi};
-use sqlx::{query_as, FromRow, MySql, Pool};
 
 pub struct Api {
-    db: Pool<MySql>,
+    db: Arc<Databases>,
+    cache: Arc<Cache>,
 }
 
-pub fn api(db: &Pool<MySql>) -> Api {
-    Api { db: db.clone() }
+pub fn api(db: &Arc<Databases>, cache: &Arc<Cache>) -> Api {
+    Api {
+        db: db.clone(),
+        cache: cache.clone(),
+    }
 }
 
 #[OpenApi(prefix_path = "/deaths", tag = "super::Tags::Deaths")]
@@ -18,89 +30,137 @@ impl Api {
     /// Latest Deaths
     #[oai(path = "/", method = "post")]
     async fn deaths(&self, data: Json<u32>) -> Result<Json<Vec<Death>>> {
-        let cfg = config::get();
-        if !cfg.worlds.contains_key(&data) {
-            return Err(InvalidData.into());
-        }
-
-        let deaths = query_as!(
-            DeathRow, 
-            r#"SELECT pd.id, p.id AS player_id, p.name, p.level, pd.lost_experience, pd.date FROM player_deaths AS pd INNER JOIN players AS p ON pd.player_id = p.id WHERE p.world_id = ? ORDER BY pd.date DESC LIMIT 0, ?"#, 
-            &data.0, 
-            &cfg.deaths.page_count,
+        world(data.0)?;
+        let deaths = self
+            .cache
+            .get(
+                format!("deaths/{}", data.0),
+                config::get().cache.deaths,
+                || async {
+                    let filter = DeathFilter {
+                        world: Some(data.0),
+                        ..Default::default()
+                    };
+                    Ok(self.search(&filter).await?.deaths)
+                },
             )
-            .fetch_all(&self.db)
-            .await
-            .context("deaths")?;
-
-        let mut ret = Vec::new();
-
-        for death in deaths {
-            let killers = query_as!(
-                KillerRow,
-                r#"SELECT p.id, COALESCE(p.name, ek.name, '?') AS name FROM killers k LEFT JOIN environment_killers ek ON k.id = ek.kill_id LEFT JOIN player_killers pk ON k.id = pk.kill_id LEFT JOIN players p ON p.id = pk.player_id WHERE k.death_id = ? ORDER BY k.final_hit DESC, k.id ASC"#,
-                death.id,
-                )
-                .fetch_all(&self.db)
-                .await
-                .context(format!("death {} killers", death.id))?
-                .into_iter()
-                .map(|o| {
-                    let mut name = o.name;
-                    if o.id.is_none() {
-                        name = if let Some(stripped) = name.strip_prefix("a ") {
-                            stripped.to_owned()
-                        } else if let Some(stripped) = name.strip_prefix("an ") {
-                            stripped.to_owned()
-                        } else {
-                            name
-                        };
-                    }
-                    DeathKiller { id: o.id, name }
-                })
-                .collect::<Vec<_>>();
-
-            ret.push(Death{ 
+            .await?;
+        Ok(Json(deaths))
+    }
+
+    /// Search Deaths
+    #[oai(path = "/search", method = "post")]
+    async fn search_deaths(&self, data: Json<DeathsData>) -> Result<Json<DeathsPage>> {
+        world(data.world)?;
+        let filter = DeathFilter {
+            world: Some(data.world),
+            cursor: data.cursor.map(|c| (c.date, c.id)),
+            min_level: data.min_level,
+            pvp_only: data.pvp_only,
+            from: data.from,
+            to: data.to,
+            character: data.character,
+        };
+        Ok(Json(self.search(&filter).await?))
+    }
+
+    /// Character Deaths
+    #[oai(path = "/character", method = "post")]
+    async fn character(&self, data: Json<CharacterDeathsData>) -> Result<Json<DeathsPage>> {
+        world(data.world)?;
+        let filter = DeathFilter {
+            world: Some(data.world),
+            cursor: data.cursor.map(|c| (c.date, c.id)),
+            character: Some(data.id),
+            ..Default::default()
+        };
+        Ok(Json(self.search(&filter).await?))
+    }
+}
+
+impl Api {
+    async fn search(&self, filter: &DeathFilter) -> anyhow::Result<DeathsPage> {
+        let count = config::get().deaths.page_count;
+        let db = self.db.world(filter.world.context("deaths world")?)?;
+        let deaths = repository::deaths(db, filter, count).await?;
+
+        let next = match deaths.last() {
+            Some(last) if deaths.len() == count as usize => Some(DeathCursor {
+                date: last.date,
+                id: last.id,
+            }),
+            _ => None,
+        };
+
+        let deaths = deaths
+            .into_iter()
+            .map(|death| Death {
+                killers: death
+                    .killers
+                    .into_iter()
+                    .map(|k| DeathKiller {
+                        id: k.id,
+                        name: k.name,
+                    })
+                    .collect(),
                 id: death.player_id,
-                name: death.name, 
+                name: death.name,
                 level: death.level,
                 lost_experience: death.lost_experience,
                 date: death.date,
-                killers,
-            });
-        }
+            })
+            .collect();
 
-        Ok(Json(ret))
+        Ok(DeathsPage { deaths, next })
     }
 }
 
-#[derive(FromRow)]
-struct DeathRow {
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct DeathsData {
+    world: u32,
+    cursor: Option<DeathCursor>,
+    /// Lowest level the character died at
+    min_level: Option<u32>,
+    #[oai(default)]
+    pvp_only: bool,
+    from: Option<u64>,
+    to: Option<u64>,
+    character: Option<i32>,
+}
+
+#[derive(Object)]
+struct CharacterDeathsData {
+    world: u32,
     id: i32,
-    player_id: i32,
-    name: String,
-    level: u32,
-    lost_experience: u64,
-    date: u64,
+    cursor: Option<DeathCursor>,
 }
- 
-#[derive(FromRow)]
-struct KillerRow {
-    id: Option<i32>,
-    name: String,
+
+#[derive(Object, Copy, Clone)]
+struct DeathCursor {
+    date: u64,
+    id: i32,
 }
 
 #[derive(Object)]
+#[oai(skip_serializing_if_is_none = true)]
+struct DeathsPage {
+    deaths: Vec<Death>,
+    next: Option<DeathCursor>,
+}
+
+#[derive(Object, Clone)]
 struct Death {
     id: i32,
     name: String,
+    /// Level the character died at
     level: u32,
     lost_experience: u64,
     date: u64,
     killers: Vec<DeathKiller>,
 }
 
-#[derive(Object)]
+#[derive(Object, Clone)]
 #[oai(skip_serializing_if_is_none = true)]
 struct DeathKiller {
     id: Option<i32>,
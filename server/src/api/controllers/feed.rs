@@ -0,0 +1,146 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::services::feed::{Event, Feed};
+
+use super::prelude::*;
+use futures_util::{stream::BoxStream, SinkExt, StreamExt};
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{
+        websocket::{Message, WebSocket},
+        Data, Path,
+    },
+    Error, IntoResponse,
+};
+use poem_openapi::{payload::EventStream, types::ToJSON, Object, OpenApi, Union};
+
+pub struct Api {
+    feed: Arc<Feed>,
+}
+
+pub fn api(feed: &Arc<Feed>) -> Api {
+    Api { feed: feed.clone() }
+}
+
+#[OpenApi(prefix_path = "/feed", tag = "super::Tags::Feed")]
+impl Api {
+    /// Live Feed
+    #[oai(path = "/:world/sse", method = "get")]
+    async fn sse(
+        &self,
+        world: poem_openapi::param::Path<u32>,
+    ) -> Result<EventStream<BoxStream<'static, FeedEvent>>> {
+        let events = subscribe(&self.feed, world.0)?;
+        Ok(EventStream::new(events).keep_alive(Duration::from_secs(15)))
+    }
+}
+
+/// Live Feed over WebSocket, same events as the SSE endpoint
+#[handler]
+pub(in crate::api) async fn websocket(
+    Path(world): Path<u32>,
+    ws: WebSocket,
+    Data(feed): Data<&Arc<Feed>>,
+) -> Result<impl IntoResponse> {
+    let mut events = subscribe(feed, world)?;
+    Ok(ws.on_upgrade(move |socket| async move {
+        let (mut sink, mut source) = socket.split();
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => {
+                        if sink.send(Message::Text(event.to_json_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                message = source.next() => match message {
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    _ => {}
+                },
+            }
+        }
+    }))
+}
+
+fn subscribe(feed: &Arc<Feed>, id: u32) -> Result<BoxStream<'static, FeedEvent>> {
+    world(id)?;
+    let subscription = feed
+        .subscribe()
+        .ok_or_else(|| Error::from_status(StatusCode::SERVICE_UNAVAILABLE))?;
+    Ok(subscription.events(id).map(FeedEvent::from).boxed())
+}
+
+#[derive(Union)]
+#[oai(discriminator_name = "type")]
+enum FeedEvent {
+    #[oai(mapping = "death")]
+    Death(FeedDeath),
+    #[oai(mapping = "login")]
+    Login(FeedLogin),
+    #[oai(mapping = "logout")]
+    Logout(FeedLogout),
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct FeedDeath {
+    id: i32,
+    player_id: i32,
+    name: String,
+    level: u32,
+    date: u64,
+}
+
+#[derive(Object)]
+struct FeedLogin {
+    id: i32,
+    name: String,
+    level: u32,
+    vocation: String,
+}
+
+#[derive(Object)]
+struct FeedLogout {
+    id: i32,
+    name: String,
+}
+
+impl From<Event> for FeedEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Death {
+                id,
+                player_id,
+                name,
+                level,
+                date,
+                ..
+            } => FeedEvent::Death(FeedDeath {
+                id,
+                player_id,
+                name,
+                level,
+                date,
+            }),
+            Event::Login {
+                id,
+                name,
+                level,
+                vocation,
+                ..
+            } => FeedEvent::Login(FeedLogin {
+                id,
+                name,
+                level,
+                vocation: crate::config::get()
+                    .vocation_name(vocation)
+                    .unwrap_or("Unknown")
+                    .to_owned(),
+            }),
+            Event::Logout { id, name, .. } => FeedEvent::Logout(FeedLogout { id, name }),
+        }
+    }
+}
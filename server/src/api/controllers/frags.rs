@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use crate::{
+    config,
+    services::{cache::Cache, databases::Databases},
+    utils::time,
+};
+
+use super::prelude::*;
+use anyhow::Context;
+use poem_openapi::{payload::Json, Object, OpenApi};
+use sqlx::{query_as, FromRow};
+
+pub struct Api {
+    db: Arc<Databases>,
+    cache: Arc<Cache>,
+}
+
+pub fn api(db: &Arc<Databases>, cache: &Arc<Cache>) -> Api {
+    Api {
+        db: db.clone(),
+        cache: cache.clone(),
+    }
+}
+
+#[OpenApi(prefix_path = "/frags", tag = "super::Tags::Frags")]
+impl Api {
+    /// Frag Highscores
+    #[oai(path = "/", method = "post")]
+    async fn frags(&self, data: Json<FragsData>) -> Result<Json<Vec<Fragger>>> {
+        world(data.world)?;
+        let key = format!(
+            "frags/{}/{}/{}/{}",
+            data.world, data.recent, data.unjustified, data.page_number
+        );
+        let frags = self
+            .cache
+            .get(key, config::get().cache.frags, || async {
+                let cfg = config::get();
+                let count = cfg.frags.page_count;
+                let skip = count * data.page_number;
+                let since = if data.recent {
+                    time::now().saturating_sub(cfg.frags.window) as u64
+                } else {
+                    0
+                };
+                query_as!(
+                    Fragger,
+                    r#"SELECT p.id, p.name, p.level, CAST(COUNT(*) AS UNSIGNED) AS "kills!: u64", CAST(SUM(k.final_hit) AS UNSIGNED) AS "final_hits!: u64", CAST(SUM(k.unjustified) AS UNSIGNED) AS "unjustified!: u64" FROM player_killers AS pk INNER JOIN killers AS k ON k.id = pk.kill_id INNER JOIN player_deaths AS pd ON pd.id = k.death_id INNER JOIN players AS p ON p.id = pk.player_id WHERE p.world_id = ? AND (? = 0 OR p.group_id < ?) AND pd.date >= ? AND (? = FALSE OR k.unjustified = 1) GROUP BY p.id, p.name, p.level ORDER BY COUNT(*) DESC, SUM(k.final_hit) DESC, p.id ASC LIMIT ?, ?"#,
+                    &data.world,
+                    cfg.staff.group,
+                    cfg.staff.group,
+                    since,
+                    data.unjustified,
+                    &skip,
+                    &count,
+                )
+                .fetch_all(self.db.world(data.world)?)
+                .await
+                .context("frags")
+            })
+            .await?;
+        Ok(Json(frags))
+    }
+
+    /// Character Frags
+    #[oai(path = "/character", method = "post")]
+    async fn character(&self, data: Json<CharacterFragsData>) -> Result<Json<Vec<Frag>>> {
+        world(data.world)?;
+        let count = config::get().frags.page_count;
+        let skip = count * data.page_number;
+        let frags = query_as!(
+            Frag,
+            r#"SELECT v.id, v.name, pd.level, pd.date, k.final_hit AS "final_hit: bool", k.unjustified AS "unjustified: bool" FROM player_killers AS pk INNER JOIN killers AS k ON k.id = pk.kill_id INNER JOIN player_deaths AS pd ON pd.id = k.death_id INNER JOIN players AS v ON v.id = pd.player_id INNER JOIN players AS p ON p.id = pk.player_id WHERE pk.player_id = ? AND p.world_id = ? ORDER BY pd.date DESC, pd.id DESC LIMIT ?, ?"#,
+            &data.id,
+            &data.world,
+            &skip,
+            &count,
+        )
+        .fetch_all(self.db.world(data.world)?)
+        .await
+        .context("character frags")?;
+        Ok(Json(frags))
+    }
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct FragsData {
+    world: u32,
+    #[oai(default)]
+    recent: bool,
+    #[oai(default)]
+    unjustified: bool,
+    page_number: u32,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct CharacterFragsData {
+    world: u32,
+    id: i32,
+    page_number: u32,
+}
+
+#[derive(Object, FromRow, Clone)]
+#[oai(rename_all = "camelCase")]
+struct Fragger {
+    id: i32,
+    name: String,
+    level: u32,
+    kills: u64,
+    final_hits: u64,
+    unjustified: u64,
+}
+
+#[derive(Object, FromRow)]
+#[oai(rename_all = "camelCase")]
+struct Frag {
+    id: i32,
+    name: String,
+    level: u32,
+    date: u64,
+    final_hit: bool,
+    unjustified: bool,
+}
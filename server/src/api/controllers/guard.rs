@@ -0,0 +1,46 @@
+use anyhow::Context;
+use sqlx::{query, MySql, Transaction};
+
+use super::prelude::*;
+
+/// Rejects mutations of a character that is logged into the game server.
+/// The row stays locked until the transaction ends, so the game server can't
+/// log the character in before the mutation is committed
+pub(super) async fn character_offline(tx: &mut Transaction<'_, MySql>, id: i32) -> Result<()> {
+    if query!(
+        r#"SELECT online AS "online: bool" FROM players WHERE id=? FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut **tx)
+    .await
+    .context("character online")?
+    .is_some_and(|r| r.online)
+    {
+        return Err(CharacterOnline.into());
+    }
+    Ok(())
+}
+
+/// Rejects mutations of an account while any of its characters is logged in.
+/// Run it in a transaction of every database from `Databases::all`, the
+/// account's characters stay locked until those transactions end.
+/// Nothing deletes accounts yet, it is kept for the first path that does
+#[allow(dead_code)]
+pub(super) async fn account_offline(
+    tx: &mut Transaction<'_, MySql>,
+    account_id: i32,
+) -> Result<()> {
+    if query!(
+        r#"SELECT online AS "online: bool" FROM players WHERE account_id=? FOR UPDATE"#,
+        account_id
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .context("account online")?
+    .iter()
+    .any(|r| r.online)
+    {
+        return Err(AccountCharacterOnline.into());
+    }
+    Ok(())
+}
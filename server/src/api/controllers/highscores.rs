@@ -1,151 +1,315 @@
-use std::{collections::HashMap, fmt, sync::Mutex};
+use std::{fmt, sync::Arc};
 
-use crate::{config, utils::time};
+use crate::{
+    config,
+    services::{
+        cache::Cache,
+        databases::Databases,
+        highscores::{self, Category, Entry, Filter},
+    },
+    utils::time,
+};
 
 use super::prelude::*;
 use anyhow::Context;
-use poem_openapi::{payload::Json, Enum, Object, OpenApi};
-use sqlx::{query_as, FromRow, MySql, Pool};
+use poem_openapi::{
+    payload::Json,
+    types::{ParseFromJSON, ToJSON},
+    Enum, Object, OpenApi,
+};
+use sqlx::{query, query_as, FromRow};
 
 pub struct Api {
-    db: Pool<MySql>,
-    vocation_cache: Mutex<HashMap<u32, VocationHighscoresCache>>,
+    db: Arc<Databases>,
+    cache: Arc<Cache>,
 }
 
-pub fn api(db: &Pool<MySql>) -> Api {
+pub fn api(db: &Arc<Databases>, cache: &Arc<Cache>) -> Api {
     Api {
         db: db.clone(),
-        vocation_cache: Mutex::new(HashMap::new()),
+        cache: cache.clone(),
     }
 }
 
 #[OpenApi(prefix_path = "/highscores", tag = "super::Tags::Highscores")]
 impl Api {
+    /// Highscores
+    #[oai(path = "/", method = "post")]
+    async fn highscores(&self, data: Json<HighscoresData>) -> Result<Json<Page<Highscores>>> {
+        let filter = filter(data.world, data.vocation.as_deref(), data.page_number)?;
+        let key = format!(
+            "highscores/all/{:?}/{:?}/{:?}/{}",
+            data.category, data.world, data.vocation, data.page_number
+        );
+        let page = self
+            .cache
+            .get(key, config::get().cache.highscores, || async {
+                let count = config::get().highscores.page_count;
+                let page = highscores::page(
+                    &self.db,
+                    data.category,
+                    filter,
+                    count * data.page_number,
+                    count,
+                )
+                .await?;
+                Ok(Page::new(page, data.world, data.page_number, |e| {
+                    Highscores {
+                        rank: e.rank,
+                        id: e.id,
+                        name: e.name,
+                        vocation: config::get()
+                            .vocation_name(e.vocation)
+                            .unwrap_or("Unknown")
+                            .to_owned(),
+                        world: e.world_id,
+                        level: e.value as u32,
+                        points: e.points,
+                    }
+                }))
+            })
+            .await?;
+        Ok(Json(page))
+    }
+
     /// Level Highscores
     #[oai(path = "/level", method = "post")]
-    async fn level(&self, data: Json<LevelHighscoresData>) -> Result<Json<Vec<LevelHighscores>>> {
+    async fn level(
+        &self,
+        data: Json<LevelHighscoresData>,
+    ) -> Result<Json<Page<LevelHighscores>>> {
+        let filter = filter(data.world, data.vocation.as_deref(), data.page_number)?;
+        let key = format!(
+            "highscores/level/{:?}/{:?}/{}",
+            data.world, data.vocation, data.page_number
+        );
+        let page = self
+            .cache
+            .get(key, config::get().cache.highscores, || async {
+                let count = config::get().highscores.page_count;
+                let page = highscores::page(
+                    &self.db,
+                    Category::Level,
+                    filter,
+                    count * data.page_number,
+                    count,
+                )
+                .await?;
+                Ok(Page::new(page, data.world, data.page_number, |e| {
+                    LevelHighscores {
+                        rank: e.rank,
+                        id: e.id,
+                        name: e.name,
+                        level: e.value as u32,
+                        experience: e.points,
+                    }
+                }))
+            })
+            .await?;
+        Ok(Json(page))
+    }
+
+    /// Skill Highscores
+    #[oai(path = "/skill", method = "post")]
+    async fn skill(
+        &self,
+        data: Json<SkillHighscoresData>,
+    ) -> Result<Json<Page<SkillHighscores>>> {
+        let filter = filter(data.world, data.vocation.as_deref(), data.page_number)?;
+        let key = format!(
+            "highscores/skill/{}/{:?}/{:?}/{}",
+            data.skill, data.world, data.vocation, data.page_number
+        );
+        let page = self
+            .cache
+            .get(key, config::get().cache.highscores, || async {
+                let count = config::get().highscores.page_count;
+                let page = highscores::page(
+                    &self.db,
+                    data.skill.into(),
+                    filter,
+                    count * data.page_number,
+                    count,
+                )
+                .await?;
+                Ok(Page::new(page, data.world, data.page_number, |e| {
+                    SkillHighscores {
+                        rank: e.rank,
+                        id: e.id,
+                        name: e.name,
+                        level: e.value as u32,
+                    }
+                }))
+            })
+            .await?;
+        Ok(Json(page))
+    }
+
+    /// Character ranks in every category on its world
+    #[oai(path = "/character", method = "post")]
+    async fn character(&self, character: Json<CharacterRef>) -> Result<Json<CharacterRanks>> {
+        world(character.world)?;
+        let db = self.db.world(character.world)?;
+        let record = query!(
+            "SELECT vocation FROM players WHERE id=? AND world_id=? AND NOT deleted",
+            character.id,
+            character.world
+        )
+        .fetch_optional(db)
+        .await
+        .context("record")?
+        .ok_or(CharacterNotExists)?;
+
+        let world = Some(character.world);
+        let ranks = highscores::ranks(
+            db,
+            Filter {
+                world,
+                vocations: None,
+            },
+            character.id,
+        )
+        .await?;
+        let group = config::get()
+            .character
+            .vocations
+            .iter()
+            .find(|(_, ids)| ids.contains(&record.vocation));
+        let vocation_ranks = match group {
+            Some((_, ids)) => Some(
+                highscores::ranks(
+                    db,
+                    Filter {
+                        world,
+                        vocations: Some(ids.as_slice()),
+                    },
+                    character.id,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        Ok(Json(CharacterRanks {
+            world: character.world,
+            vocation: group.map(|(name, _)| name.clone()),
+            ranks: ranks
+                .into_iter()
+                .enumerate()
+                .map(|(i, (category, rank))| CharacterRank {
+                    category,
+                    rank,
+                    vocation_rank: vocation_ranks.as_ref().map(|r| r[i].1),
+                })
+                .collect(),
+        }))
+    }
+
+    /// Top experience gainers over the period
+    #[oai(path = "/powergamers", method = "post")]
+    async fn powergamers(&self, data: Json<PowergamersData>) -> Result<Json<Vec<Powergamer>>> {
         let cfg = config::get();
-        if !cfg.worlds.contains_key(&data.world) {
-            return Err(InvalidData.into());
-        }
+        world(data.world)?;
         let count = cfg.highscores.page_count;
         let skip = count * data.page_number;
+        let since = time::now().saturating_sub(data.period.seconds()) as u64;
 
         let characters = query_as!(
-            LevelHighscores,
-            r#"SELECT id, name, level, experience FROM players WHERE group_id < 3 AND world_id = ? ORDER BY experience DESC LIMIT ?, ?"#,
+            Powergamer,
+            r#"SELECT p.id, p.name, p.level, CAST(p.experience AS SIGNED) - CAST(s.experience AS SIGNED) AS "gain!: i64" FROM players AS p INNER JOIN player_snapshots AS s ON s.player_id = p.id INNER JOIN (SELECT player_id, MIN(time) AS time FROM player_snapshots WHERE time >= ? GROUP BY player_id) AS f ON f.player_id = s.player_id AND f.time = s.time WHERE p.world_id = ? AND (? = 0 OR p.group_id < ?) AND NOT p.deleted ORDER BY CAST(p.experience AS SIGNED) - CAST(s.experience AS SIGNED) DESC, p.id ASC LIMIT ?, ?"#,
+            since,
             &data.world,
+            cfg.staff.group,
+            cfg.staff.group,
             &skip,
             &count,
         )
-        .fetch_all(&self.db)
+        .fetch_all(self.db.world(data.world)?)
         .await
-        .context("level")?;
+        .context("powergamers")?;
         Ok(Json(characters))
     }
 
-    /// Skill Highscores
-    #[oai(path = "/skill", method = "post")]
-    async fn skill(&self, data: Json<SkillHighscoresData>) -> Result<Json<Vec<SkillHighscores>>> {
-        let cfg = config::get();
-        if !cfg.worlds.contains_key(&data.world) {
-            return Err(InvalidData.into());
-        }
-        let count = cfg.highscores.page_count;
-        let skip = count * data.page_number;
-
-        let characters = match data.skill {
-            Skill::Fist => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_fist AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_fist DESC, skill_fist_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Club => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_club AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_club DESC, skill_club_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Sword => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_sword AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_sword DESC, skill_sword_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Axe => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_axe AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_axe DESC, skill_axe_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Distance => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_dist AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_dist DESC, skill_dist_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Shielding => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_shielding  AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_shielding DESC, skill_shielding_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Fishing => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, skill_fishing AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY skill_fishing DESC, skill_fishing_tries DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-            Skill::Magic => query_as!(
-                SkillHighscores,
-                r#"SELECT id, name, maglevel AS "level: u32" FROM players WHERE group_id < 3 AND world_id = ? ORDER BY maglevel DESC, manaspent DESC LIMIT ?, ?"#,
-                &data.world, &skip, &count).fetch_all(&self.db).await,
-        }.context(data.skill)?;
-
-        Ok(Json(characters))
+    /// Character progression over the period
+    #[oai(path = "/progression", method = "post")]
+    async fn progression(&self, data: Json<ProgressionData>) -> Result<Json<Vec<Progression>>> {
+        world(data.world)?;
+        let since = time::now().saturating_sub(data.period.seconds()) as u64;
+        let snapshots = query_as!(
+            Progression,
+            r#"SELECT time, level, experience, maglevel, skill_fist, skill_club, skill_sword, skill_axe, skill_dist, skill_shielding, skill_fishing FROM player_snapshots WHERE player_id = ? AND time >= ? ORDER BY time ASC"#,
+            &data.id,
+            since,
+        )
+        .fetch_all(self.db.world(data.world)?)
+        .await
+        .context("progression")?;
+        Ok(Json(snapshots))
     }
 
     /// Vocation Highscores
     #[oai(path = "/vocation", method = "post")]
     async fn vocation(&self, data: Json<u32>) -> Result<Json<Vec<VocationHighscores>>> {
         let cfg = config::get();
-        if !cfg.worlds.contains_key(&data.0) {
-            return Err(InvalidData.into());
-        }
+        world(data.0)?;
 
-        let cache_time = cfg.highscores.vocation_cache_time;
-        if cache_time > 0 {
-            let cache = self.vocation_cache.lock().unwrap();
-            if let Some(cache) = cache.get(&data.0) {
-                if cache.time + cache_time > time::now() {
-                    return Ok(Json(cache.vocation_highscores.clone()));
+        let key = format!("highscores/vocation/{}", data.0);
+        let ret = self
+            .cache
+            .get(key, cfg.cache.highscores, || async {
+                let mut ret = Vec::new();
+                for (name, vocations) in &cfg.character.vocations {
+                    let filter = Filter {
+                        world: Some(data.0),
+                        vocations: Some(vocations.as_slice()),
+                    };
+                    if let Some(e) =
+                        highscores::entries(self.db.world(data.0)?, Category::Level, filter, 0, 1)
+                            .await?
+                            .pop()
+                    {
+                        ret.push(VocationHighscores {
+                            id: e.id,
+                            name: e.name,
+                            level: e.value as u32,
+                            vocation: name.clone(),
+                        });
+                    }
                 }
-            }
-        }
+                Ok(ret)
+            })
+            .await?;
+        Ok(Json(ret))
+    }
+}
 
-        let mut ret = Vec::new();
-        for (name, vocations) in &cfg.character.vocations {
-            let params = format!("?{}", ", ?".repeat(vocations.len() - 1));
-            let query_str = format!(
-                r#"SELECT id, name, level, ? AS vocation FROM players WHERE world_id = ? AND vocation IN ( {} ) ORDER BY experience DESC LIMIT 1"#,
-                params
-            );
-            let mut query = sqlx::query_as::<_, VocationHighscores>(&query_str)
-                .bind(&name)
-                .bind(&data.0);
-            for i in vocations {
-                query = query.bind(i);
-            }
-            if let Some(row) = query.fetch_optional(&self.db).await.context("vocation")? {
-                ret.push(row);
-            }
+fn filter(world: Option<u32>, vocation: Option<&str>, page_number: u32) -> Result<Filter> {
+    match world {
+        Some(id) => {
+            super::world(id)?;
         }
-
-        if cache_time > 0 {
-            let mut cache = self.vocation_cache.lock().unwrap();
-            cache.insert(
-                data.0,
-                VocationHighscoresCache {
-                    vocation_highscores: ret.clone(),
-                    time: time::now(),
-                },
-            );
+        None if page_number >= config::get().highscores.max_pages => {
+            return Err(InvalidData.into());
         }
-        Ok(Json(ret))
+        None => {}
     }
+    Filter::new(world, vocation).ok_or_else(|| InvalidData.into())
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct HighscoresData {
+    category: Category,
+    world: Option<u32>,
+    vocation: Option<String>,
+    page_number: u32,
 }
 
 #[derive(Object)]
 #[oai(rename_all = "camelCase")]
 struct LevelHighscoresData {
-    world: u32,
+    world: Option<u32>,
+    vocation: Option<String>,
     page_number: u32,
 }
 
@@ -153,12 +317,45 @@ struct LevelHighscoresData {
 #[oai(rename_all = "camelCase")]
 struct SkillHighscoresData {
     skill: Skill,
+    world: Option<u32>,
+    vocation: Option<String>,
+    page_number: u32,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct PowergamersData {
     world: u32,
+    period: Period,
     page_number: u32,
 }
 
+#[derive(Object)]
+struct ProgressionData {
+    world: u32,
+    id: i32,
+    period: Period,
+}
+
+#[derive(Enum, Copy, Clone)]
+enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn seconds(self) -> usize {
+        match self {
+            Period::Day => 24 * 60 * 60,
+            Period::Week => 7 * 24 * 60 * 60,
+            Period::Month => 30 * 24 * 60 * 60,
+        }
+    }
+}
+
 #[derive(Debug, Enum, Copy, Clone, PartialEq)]
-enum Skill {
+pub(super) enum Skill {
     Fist = 0,
     Club = 1,
     Sword = 2,
@@ -169,33 +366,149 @@ enum Skill {
     Magic = 7,
 }
 
+impl Skill {
+    pub(super) const ALL: [Skill; 8] = [
+        Skill::Fist,
+        Skill::Club,
+        Skill::Sword,
+        Skill::Axe,
+        Skill::Distance,
+        Skill::Shielding,
+        Skill::Fishing,
+        Skill::Magic,
+    ];
+}
+
 impl fmt::Display for Skill {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt::Debug::fmt(self, f)
     }
 }
 
-#[derive(Object, FromRow)]
+impl From<Skill> for Category {
+    fn from(value: Skill) -> Self {
+        match value {
+            Skill::Fist => Category::Fist,
+            Skill::Club => Category::Club,
+            Skill::Sword => Category::Sword,
+            Skill::Axe => Category::Axe,
+            Skill::Distance => Category::Distance,
+            Skill::Shielding => Category::Shielding,
+            Skill::Fishing => Category::Fishing,
+            Skill::Magic => Category::Magic,
+        }
+    }
+}
+
+#[derive(Object, Clone)]
+#[oai(
+    rename_all = "camelCase",
+    concretes(
+        concrete(name = "HighscoresPage", params(Highscores)),
+        concrete(name = "LevelHighscoresPage", params(LevelHighscores)),
+        concrete(name = "SkillHighscoresPage", params(SkillHighscores)),
+    )
+)]
+struct Page<T: ParseFromJSON + ToJSON> {
+    entries: Vec<T>,
+    total: u64,
+    page_size: u32,
+    page_count: u64,
+    page_number: u32,
+}
+
+impl<T: ParseFromJSON + ToJSON> Page<T> {
+    fn new(
+        page: highscores::Page,
+        world: Option<u32>,
+        page_number: u32,
+        map: impl FnMut(Entry) -> T,
+    ) -> Self {
+        let cfg = &config::get().highscores;
+        let mut page_count = page.total.div_ceil(cfg.page_count.max(1) as u64);
+        // Deeper pages of every world are refused
+        if world.is_none() {
+            page_count = page_count.min(cfg.max_pages as u64);
+        }
+        Self {
+            entries: page.entries.into_iter().map(map).collect(),
+            total: page.total,
+            page_size: cfg.page_count,
+            page_count,
+            page_number,
+        }
+    }
+}
+
+#[derive(Object, Clone)]
+struct Highscores {
+    rank: u64,
+    id: i32,
+    name: String,
+    vocation: String,
+    world: u32,
+    level: u32,
+    points: u64,
+}
+
+#[derive(Object, Clone)]
 struct LevelHighscores {
+    rank: u64,
     id: i32,
     name: String,
     level: u32,
     experience: u64,
 }
 
-#[derive(Object, FromRow)]
+#[derive(Object, Clone)]
 struct SkillHighscores {
+    rank: u64,
     id: i32,
     name: String,
     level: u32,
 }
 
-struct VocationHighscoresCache {
-    vocation_highscores: Vec<VocationHighscores>,
-    time: usize,
+#[derive(Object)]
+#[oai(skip_serializing_if_is_none = true)]
+struct CharacterRanks {
+    world: u32,
+    vocation: Option<String>,
+    ranks: Vec<CharacterRank>,
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+struct CharacterRank {
+    category: Category,
+    rank: u64,
+    vocation_rank: Option<u64>,
+}
+
+#[derive(Object, FromRow)]
+struct Powergamer {
+    id: i32,
+    name: String,
+    level: u32,
+    gain: i64,
+}
+
+#[derive(Object, FromRow)]
+#[oai(rename_all = "camelCase")]
+struct Progression {
+    time: u64,
+    level: u32,
+    experience: u64,
+    maglevel: u32,
+    skill_fist: u32,
+    skill_club: u32,
+    skill_sword: u32,
+    skill_axe: u32,
+    skill_dist: u32,
+    skill_shielding: u32,
+    skill_fishing: u32,
 }
 
-#[derive(Object, FromRow, Clone)]
+#[derive(Object, Clone)]
 struct VocationHighscores {
     id: i32,
     name: String,
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use crate::services::kill_statistics::Service;
+
+use super::prelude::*;
+use poem_openapi::{payload::Json, Object, OpenApi};
+
+pub struct Api {
+    statistics: Arc<Service>,
+}
+
+pub fn api(statistics: &Arc<Service>) -> Api {
+    Api {
+        statistics: statistics.clone(),
+    }
+}
+
+#[OpenApi(prefix_path = "/killstatistics", tag = "super::Tags::KillStatistics")]
+impl Api {
+    /// Kill Statistics
+    #[oai(path = "/", method = "post")]
+    async fn kill_statistics(&self, data: Json<u32>) -> Result<Json<Vec<KillStatistic>>> {
+        world(data.0)?;
+        Ok(Json(
+            self.statistics
+                .world(data.0)
+                .into_iter()
+                .map(|e| KillStatistic {
+                    name: e.name,
+                    last_day: e.last_day,
+                    last_week: e.last_week,
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Object)]
+#[oai(rename_all = "camelCase")]
+struct KillStatistic {
+    name: String,
+    last_day: u64,
+    last_week: u64,
+}
@@ -0,0 +1,150 @@
+use crate::{config, services::highscores::Category};
+
+use super::highscores::Skill;
+use poem_openapi::{param::Header, payload::Json, types::ToJSON, ApiResponse, Object, OpenApi};
+
+pub struct Api {
+    meta: Meta,
+    etag: String,
+}
+
+pub fn api() -> Api {
+    let meta = meta();
+    let etag = format!("\"{:016x}\"", fnv1a(meta.to_json_string().as_bytes()));
+    Api { meta, etag }
+}
+
+/// 64-bit FNV-1a, stable across builds unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[OpenApi(prefix_path = "/meta", tag = "super::Tags::Meta")]
+impl Api {
+    /// Server Metadata
+    #[oai(path = "/", method = "get")]
+    async fn meta(
+        &self,
+        #[oai(name = "If-None-Match")] if_none_match: Header<Option<String>>,
+    ) -> MetaResponse {
+        if if_none_match.0.as_ref() == Some(&self.etag) {
+            return MetaResponse::NotModified;
+        }
+        MetaResponse::Ok(Json(self.meta.clone()), self.etag.clone())
+    }
+}
+
+fn meta() -> Meta {
+    let cfg = config::get();
+    let mut vocations = cfg
+        .character
+        .vocations
+        .iter()
+        .map(|(name, ids)| VocationGroup {
+            name: name.clone(),
+            ids: ids.clone(),
+        })
+        .collect::<Vec<_>>();
+    vocations.sort_by(|a, b| a.ids.cmp(&b.ids));
+
+    let mut new_vocations = Vec::new();
+    for (&world, w) in &cfg.worlds {
+        let Some(new) = cfg.new_character(world) else {
+            continue;
+        };
+        for (&id, voc) in new.vocations {
+            new_vocations.push(NewVocation {
+                id,
+                world,
+                world_name: w.name.clone(),
+                vocation: voc.vocation,
+                looktype: voc.looktype,
+            });
+        }
+    }
+    new_vocations.sort_by_key(|v| (v.world, v.id));
+
+    let mut towns = cfg
+        .towns
+        .iter()
+        .map(|(&id, name)| Town {
+            id,
+            name: name.clone(),
+        })
+        .collect::<Vec<_>>();
+    towns.sort_by_key(|t| t.id);
+
+    Meta {
+        vocations,
+        new_vocations,
+        towns,
+        skills: Skill::ALL.to_vec(),
+        categories: Category::ALL.to_vec(),
+        validation: ValidationLimits {
+            min_length: cfg.validation.min_length as u32,
+            max_length: cfg.validation.max_length as u32,
+        },
+    }
+}
+
+#[derive(ApiResponse)]
+enum MetaResponse {
+    #[oai(status = 200)]
+    Ok(Json<Meta>, #[oai(header = "ETag")] String),
+    #[oai(status = 304)]
+    NotModified,
+}
+
+#[derive(Object, Clone)]
+#[oai(rename_all = "camelCase")]
+struct Meta {
+    vocations: Vec<VocationGroup>,
+    new_vocations: Vec<NewVocation>,
+    towns: Vec<Town>,
+    skills: Vec<Skill>,
+    categories: Vec<Category>,
+    validation: ValidationLimits,
+}
+
+#[derive(Object, Clone)]
+struct VocationGroup {
+    name: String,
+    ids: Vec<u32>,
+}
+
+#[derive(Object, Clone)]
+#[oai(rename_all = "camelCase")]
+struct NewVocation {
+    id: u32,
+    world: u32,
+    world_name: String,
+    vocation: u32,
+    looktype: u32,
+}
+
+#[derive(Object, Clone)]
+struct Town {
+    id: u32,
+    name: String,
+}
+
+#[derive(Object, Clone)]
+#[oai(rename_all = "camelCase")]
+struct ValidationLimits {
+    min_length: u32,
+    max_length: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_known_answers() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+}
@@ -1,15 +1,38 @@
 pub(super) mod account;
 pub(super) mod character;
 pub(super) mod deaths;
+pub(super) mod feed;
+pub(super) mod frags;
 pub(super) mod highscores;
+pub(super) mod kill_statistics;
+pub(super) mod meta;
 pub(super) mod online;
 pub(super) mod validation;
+pub(super) mod worlds;
+
+mod guard;
 
 mod prelude {
+    pub(super) use super::{world, CharacterRef};
     pub use crate::api::validation_error::ValidationError::*;
     pub use poem::Result;
 }
 
+/// Configured world, unknown ids are rejected as `InvalidData`
+pub(super) fn world(id: u32) -> poem::Result<&'static crate::config::World> {
+    crate::config::get()
+        .worlds
+        .get(&id)
+        .ok_or_else(|| prelude::InvalidData.into())
+}
+
+/// Character ids are only unique within the database of their world
+#[derive(poem_openapi::Object)]
+pub(super) struct CharacterRef {
+    pub world: u32,
+    pub id: i32,
+}
+
 #[derive(poem_openapi::Tags)]
 pub enum Tags {
     Account,
@@ -17,5 +40,10 @@ pub enum Tags {
     Highscores,
     Online,
     Deaths,
+    Frags,
+    KillStatistics,
+    Feed,
+    Worlds,
+    Meta,
     Validation,
 }
@@ -1,16 +1,26 @@
-use crate::config;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    config,
+    services::{cache::Cache, databases::Databases, online, repository},
+    utils::time,
+};
 
 use super::prelude::*;
 use anyhow::Context;
-use poem_openapi::{payload::Json, Object, OpenApi};
-use sqlx::{query_as, FromRow, MySql, Pool};
+use poem_openapi::{payload::Json, Enum, Object, OpenApi};
+use sqlx::{query, query_as};
 
 pub struct Api {
-    db: Pool<MySql>,
+    db: Arc<Databases>,
+    cache: Arc<Cache>,
 }
 
-pub fn api(db: &Pool<MySql>) -> Api {
-    Api { db: db.clone() }
+pub fn api(db: &Arc<Databases>, cache: &Arc<Cache>) -> Api {
+    Api {
+        db: db.clone(),
+        cache: cache.clone(),
+    }
 }
 
 #[OpenApi(prefix_path = "/online", tag = "super::Tags::Online")]
@@ -19,52 +29,148 @@ impl Api {
     #[oai(path = "/", method = "post")]
     async fn online(&self, data: Json<u32>) -> Result<Json<Vec<OnlinePlayer>>> {
         let cfg = config::get();
-        if !cfg.worlds.contains_key(&data) {
-            return Err(InvalidData.into());
-        }
+        world(data.0)?;
 
-        let characters = query_as!(
-            OnlinePlayerRow,
-            r#"SELECT id, name, level, vocation FROM players WHERE online = 1 AND group_id < 3 AND world_id = ? ORDER BY experience DESC"#,
-            &data.0,
-            )
-            .fetch_all(&self.db)
-            .await
-            .context("online")?
-            .into_iter()
-            .map(|o| {
-                let mut vocstr = "Unknown";
-                'l: for (k, v) in &cfg.character.vocations {
-                    for voc in v {
-                        if &o.vocation == voc {
-                            vocstr = k;
-                            break 'l;
-                        }
-                    }
-                }
-                OnlinePlayer {
-                    id: o.id,
-                    name: o.name,
-                    level: o.level,
-                    vocation: vocstr.to_owned(),
-                }})
-        .collect::<Vec<_>>();
+        let characters = self
+            .cache
+            .get(format!("online/{}", data.0), cfg.cache.online, || async {
+                Ok(repository::online(self.db.world(data.0)?, data.0)
+                    .await?
+                    .into_iter()
+                    .map(|o| OnlinePlayer {
+                        id: o.id,
+                        name: o.name,
+                        level: o.level,
+                        vocation: cfg
+                            .vocation_name(o.vocation)
+                            .unwrap_or("Unknown")
+                            .to_owned(),
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .await?;
         Ok(Json(characters))
     }
+
+    /// Online Record
+    ///
+    /// Same record as the worlds list, taken from the server's `server_record`
+    #[oai(path = "/record", method = "post")]
+    async fn record(&self, data: Json<u32>) -> Result<Json<Option<OnlineRecord>>> {
+        world(data.0)?;
+        let record = online::records(self.db.world(data.0)?)
+            .await?
+            .remove(&data.0)
+            .map(|r| OnlineRecord {
+                count: r.count,
+                date: r.time,
+            });
+        Ok(Json(record))
+    }
+
+    /// Online History
+    #[oai(path = "/history", method = "post")]
+    async fn history(&self, data: Json<OnlineHistoryData>) -> Result<Json<Vec<OnlineSample>>> {
+        world(data.world)?;
+        let bucket = data.period.bucket();
+        let samples = query_as!(
+            OnlineSample,
+            r#"SELECT CAST(time DIV ? * ? AS UNSIGNED) AS "time!: u64", CAST(MAX(count) AS UNSIGNED) AS "count!: u32" FROM online_history WHERE world_id = ? AND time >= ? GROUP BY time DIV ? ORDER BY 1"#,
+            bucket,
+            bucket,
+            data.world,
+            time::now().saturating_sub(data.period.seconds()) as u64,
+            bucket
+        )
+        .fetch_all(self.db.world(data.world)?)
+        .await
+        .context("online history")?;
+        Ok(Json(samples))
+    }
+
+    /// Online Vocations
+    #[oai(path = "/vocations", method = "post")]
+    async fn vocations(&self, data: Json<u32>) -> Result<Json<Vec<VocationCount>>> {
+        world(data.0)?;
+        let staff = config::get().staff.group;
+        let counts: HashMap<_, _> = query!(
+            r#"SELECT vocation AS "vocation: u32", COUNT(*) AS count FROM players WHERE online = 1 AND (? = 0 OR group_id < ?) AND world_id = ? GROUP BY vocation"#,
+            staff,
+            staff,
+            data.0
+        )
+        .fetch_all(self.db.world(data.0)?)
+        .await
+        .context("online vocations")?
+        .into_iter()
+        .map(|r| (r.vocation, r.count as u32))
+        .collect();
+
+        let mut vocations = config::get()
+            .character
+            .vocations
+            .iter()
+            .map(|(name, ids)| VocationCount {
+                vocation: name.clone(),
+                count: ids.iter().filter_map(|id| counts.get(id)).sum(),
+            })
+            .collect::<Vec<_>>();
+        vocations.sort_by(|a, b| a.vocation.cmp(&b.vocation));
+        Ok(Json(vocations))
+    }
 }
 
-#[derive(FromRow)]
-struct OnlinePlayerRow {
+#[derive(Object, Clone)]
+struct OnlinePlayer {
     id: i32,
     name: String,
     level: u32,
-    vocation: u32,
+    vocation: String,
 }
 
 #[derive(Object)]
-struct OnlinePlayer {
-    id: i32,
-    name: String,
-    level: u32,
+struct OnlineRecord {
+    count: u32,
+    date: u64,
+}
+
+#[derive(Object)]
+struct OnlineHistoryData {
+    world: u32,
+    period: HistoryPeriod,
+}
+
+#[derive(Enum, Copy, Clone)]
+enum HistoryPeriod {
+    Day,
+    Week,
+}
+
+impl HistoryPeriod {
+    fn seconds(self) -> usize {
+        match self {
+            HistoryPeriod::Day => 24 * 60 * 60,
+            HistoryPeriod::Week => 7 * 24 * 60 * 60,
+        }
+    }
+
+    /// Chart resolution, the peak of each bucket is kept
+    fn bucket(self) -> u64 {
+        match self {
+            HistoryPeriod::Day => 15 * 60,
+            HistoryPeriod::Week => 60 * 60,
+        }
+    }
+}
+
+#[derive(Object)]
+struct OnlineSample {
+    time: u64,
+    count: u32,
+}
+
+#[derive(Object)]
+struct VocationCount {
     vocation: String,
+    count: u32,
 }
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    config::{self, PvpType},
+    services::{cache::Cache, databases::Databases, online, status},
+};
+
+use super::prelude::*;
+use anyhow::Context;
+use chrono::NaiveDate;
+use poem_openapi::{param::Path, payload::Json, Enum, Object, OpenApi};
+use sqlx::query;
+use tracing::debug;
+
+pub struct Api {
+    db: Arc<Databases>,
+    cache: Arc<Cache>,
+}
+
+pub fn api(db: &Arc<Databases>, cache: &Arc<Cache>) -> Api {
+    Api {
+        db: db.clone(),
+        cache: cache.clone(),
+    }
+}
+
+#[OpenApi(prefix_path = "/worlds", tag = "super::Tags::Worlds")]
+impl Api {
+    /// Worlds
+    #[oai(path = "/", method = "get")]
+    async fn worlds(&self) -> Result<Json<Vec<World>>> {
+        let worlds = self
+            .cache
+            .get("worlds/list".to_owned(), config::get().cache.worlds, || {
+                self.load()
+            })
+            .await?;
+        Ok(Json(worlds))
+    }
+
+    /// World Status
+    #[oai(path = "/:id/status", method = "get")]
+    async fn status(&self, id: Path<u32>) -> Result<Json<WorldStatus>> {
+        let w = world(id.0)?;
+        if !w.visible || !w.status.enabled {
+            return Err(InvalidData.into());
+        }
+        let status = self
+            .cache
+            .get(
+                format!("worlds/status/{}", id.0),
+                config::get().cache.status,
+                || async {
+                    Ok(match status::query(w.ip, &w.status).await {
+                        Ok(s) => WorldStatus {
+                            online: true,
+                            uptime: s.uptime,
+                            players_online: s.players_online,
+                            players_max: s.players_max,
+                            players_peak: s.players_peak,
+                            motd: s.motd,
+                            map: s.map,
+                            server: s.server,
+                            version: s.version,
+                            client_version: s.client_version,
+                        },
+                        Err(err) => {
+                            debug!("World {} status: {:?}", id.0, err);
+                            WorldStatus::default()
+                        }
+                    })
+                },
+            )
+            .await?;
+        Ok(Json(status))
+    }
+}
+
+impl Api {
+    async fn load(&self) -> anyhow::Result<Vec<World>> {
+        let staff = config::get().staff.group;
+        let mut online = HashMap::new();
+        let mut records = HashMap::new();
+        for db in self.db.all() {
+            let worlds = self.db.worlds_in(db);
+            for r in query!(
+                r#"SELECT world_id AS "world_id: u32", COUNT(*) AS count FROM players WHERE online = 1 AND (? = 0 OR group_id < ?) GROUP BY world_id"#,
+                staff,
+                staff
+            )
+            .fetch_all(db)
+            .await
+            .context("online")?
+            {
+                if worlds.contains(&r.world_id) {
+                    online.insert(r.world_id, r.count as u32);
+                }
+            }
+
+            for (id, r) in online::records(db).await? {
+                if worlds.contains(&id) {
+                    records.insert(
+                        id,
+                        OnlineRecord {
+                            count: r.count,
+                            date: r.time,
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut worlds = config::get()
+            .worlds
+            .iter()
+            .filter(|(_, w)| w.visible)
+            .map(|(&id, w)| World {
+                id,
+                name: w.name.clone(),
+                ip: w.ip.to_string(),
+                port: w.port,
+                pvp_type: w.pvp_type.into(),
+                location: w.location.clone(),
+                created: w.created,
+                premium_only: w.premium_only,
+                online: online.get(&id).copied().unwrap_or_default(),
+                record: records.remove(&id),
+            })
+            .collect::<Vec<_>>();
+        worlds.sort_by_key(|w| w.id);
+        Ok(worlds)
+    }
+}
+
+#[derive(Object, Clone)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+struct World {
+    id: u32,
+    name: String,
+    ip: String,
+    port: u16,
+    pvp_type: WorldPvpType,
+    location: String,
+    created: Option<NaiveDate>,
+    premium_only: bool,
+    online: u32,
+    record: Option<OnlineRecord>,
+}
+
+#[derive(Object, Clone, Default)]
+#[oai(rename_all = "camelCase", skip_serializing_if_is_none = true)]
+struct WorldStatus {
+    online: bool,
+    uptime: Option<u64>,
+    players_online: Option<u32>,
+    players_max: Option<u32>,
+    players_peak: Option<u32>,
+    motd: Option<String>,
+    map: Option<String>,
+    server: Option<String>,
+    version: Option<String>,
+    client_version: Option<String>,
+}
+
+#[derive(Object, Clone)]
+struct OnlineRecord {
+    count: u32,
+    date: u64,
+}
+
+#[derive(Enum, Copy, Clone)]
+#[oai(rename_all = "snake_case")]
+enum WorldPvpType {
+    Pvp,
+    NoPvp,
+    PvpEnforced,
+}
+
+impl From<PvpType> for WorldPvpType {
+    fn from(value: PvpType) -> Self {
+        match value {
+            PvpType::Pvp => WorldPvpType::Pvp,
+            PvpType::NoPvp => WorldPvpType::NoPvp,
+            PvpType::PvpEnforced => WorldPvpType::PvpEnforced,
+        }
+    }
+}
@@ -1,30 +1,50 @@
 use std::sync::Arc;
 
 use poem::{
-    endpoint::StaticFilesEndpoint, http::StatusCode, middleware::CatchPanic, Endpoint, EndpointExt,
-    IntoEndpoint, Middleware, Route,
+    endpoint::StaticFilesEndpoint, get, http::StatusCode, middleware::CatchPanic, post, Endpoint,
+    EndpointExt, IntoEndpoint, Middleware, Route,
 };
 use poem_openapi::OpenApiService;
-use sqlx::{MySql, Pool};
 use tracing::error;
 
-use crate::{config, services::jwt};
+use crate::{
+    config,
+    services::{
+        cache::Cache, databases::Databases, feed::Feed, jwt,
+        kill_statistics::Service as KillStatistics,
+    },
+};
 
+pub mod client_login;
 pub mod controllers;
 pub mod jwt_bearer;
+pub mod name_policy;
 pub mod trace_error;
 pub mod validation_error;
 
-pub fn routes(db: &Pool<MySql>, jwt: jwt::Service) -> impl IntoEndpoint {
+pub fn routes(
+    db: &Arc<Databases>,
+    jwt: jwt::Service,
+    names: name_policy::NamePolicy,
+    statistics: &Arc<KillStatistics>,
+    feed: &Arc<Feed>,
+    cache: &Arc<Cache>,
+) -> impl IntoEndpoint {
     let jwt = &Arc::new(jwt);
+    let names = &Arc::new(names);
     use controllers::*;
     let controllers = (
         validation::Api,
         account::api(db, jwt),
-        character::api(db),
-        highscores::api(db),
-        deaths::api(db),
-        online::api(db),
+        character::api(db, names, cache),
+        highscores::api(db, cache),
+        deaths::api(db, cache),
+        frags::api(db, cache),
+        kill_statistics::api(statistics),
+        feed::api(feed),
+        online::api(db, cache),
+        worlds::api(db, cache),
+        meta::api(),
     );
 
     let prefix = &config::get().api.prefix;
@@ -32,15 +52,24 @@ pub fn routes(db: &Pool<MySql>, jwt: jwt::Service) -> impl IntoEndpoint {
     let api = OpenApiService::new(controllers, &config::get().api.name, "1.0").url_prefix(prefix);
     let docs = api.swagger_ui();
 
-    Route::new()
+    let mut route = Route::new()
         .nest(
             "/",
             StaticFilesEndpoint::new("./wwwroot")
                 .index_file("index.html")
                 .fallback_to_index(),
         )
+        .at(
+            format!("{}/feed/:world/ws", prefix.trim_end_matches('/')),
+            get(feed::websocket).data(feed.clone()),
+        )
         .nest(prefix, api)
-        .nest("/swagger", docs)
+        .nest("/swagger", docs);
+    let login = &config::get().login;
+    if login.enabled {
+        route = route.at(&login.path, post(client_login::login).data(db.clone()));
+    }
+    route
         .with(catch_panic())
         .with(trace_error::TraceError)
 }
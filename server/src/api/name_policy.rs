@@ -0,0 +1,228 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use tracing::info;
+
+use crate::{
+    api::validation_error::ValidationError,
+    config::{self, Names},
+};
+
+pub struct NamePolicy {
+    forbidden: Vec<String>,
+    /// Lowercase for matching, as configured for the error
+    reserved: Vec<(String, String)>,
+    max_words: usize,
+    max_repeated: usize,
+    creatures: HashSet<String>,
+}
+
+pub fn new() -> Result<NamePolicy> {
+    let cfg = &config::get().character.names;
+    let mut forbidden = cfg.forbidden.clone();
+    for path in &cfg.forbidden_files {
+        let words = fs::read_to_string(path).with_context(|| format!("{}", path.display()))?;
+        forbidden.extend(
+            words
+                .lines()
+                .filter(|w| !w.trim_start().starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+
+    let mut creatures = HashSet::new();
+    if let Some(dir) = &cfg.data_dir {
+        load_creatures(dir, &mut creatures)?;
+    }
+    let policy = with_words(cfg, forbidden, creatures);
+    info!(
+        "Loaded {} forbidden words and {} creature names",
+        policy.forbidden.len(),
+        policy.creatures.len()
+    );
+    Ok(policy)
+}
+
+/// Blank words and prefixes are dropped, they would match every name
+fn with_words(cfg: &Names, forbidden: Vec<String>, creatures: HashSet<String>) -> NamePolicy {
+    let words = |list: Vec<String>| -> Vec<String> {
+        list.into_iter()
+            .map(|w| w.trim().to_owned())
+            .filter(|w| !w.is_empty())
+            .collect()
+    };
+    NamePolicy {
+        forbidden: words(forbidden)
+            .into_iter()
+            .map(|w| w.to_lowercase())
+            .collect(),
+        reserved: words(cfg.reserved_prefixes.clone())
+            .into_iter()
+            .map(|p| (p.to_lowercase(), p))
+            .collect(),
+        max_words: cfg.max_words,
+        max_repeated: cfg.max_repeated,
+        creatures,
+    }
+}
+
+impl NamePolicy {
+    pub fn check(&self, name: &str) -> Result<(), ValidationError> {
+        let lower = name.to_lowercase();
+        if let Some(word) = self.forbidden.iter().find(|w| lower.contains(w.as_str())) {
+            return Err(ValidationError::NameForbidden { word: word.clone() });
+        }
+        if let Some((_, prefix)) = self.reserved.iter().find(|(p, _)| lower.starts_with(p)) {
+            return Err(ValidationError::NameReserved {
+                prefix: prefix.clone(),
+            });
+        }
+        if self.max_words > 0 && name.split_whitespace().count() > self.max_words {
+            return Err(ValidationError::NameTooManyWords {
+                max: self.max_words,
+            });
+        }
+        if self.max_repeated > 0 && repeated_letters(&lower) > self.max_repeated {
+            return Err(ValidationError::NameRepeatedLetters {
+                max: self.max_repeated,
+            });
+        }
+        if self.creatures.contains(&lower) {
+            return Err(ValidationError::NameCreature);
+        }
+        Ok(())
+    }
+}
+
+fn repeated_letters(name: &str) -> usize {
+    let mut max = 0;
+    let mut count = 0;
+    let mut prev = None;
+    for c in name.chars() {
+        count = if prev == Some(c) { count + 1 } else { 1 };
+        prev = Some(c);
+        max = max.max(count);
+    }
+    max
+}
+
+fn load_creatures(dir: &Path, creatures: &mut HashSet<String>) -> Result<()> {
+    let monsters = dir.join("monster").join("monsters.xml");
+    let xml = fs::read_to_string(&monsters).with_context(|| format!("{}", monsters.display()))?;
+    let re = Regex::new(r#"<monster\s+name="([^"]+)""#).context("monster pattern")?;
+    creatures.extend(re.captures_iter(&xml).map(|c| c[1].to_lowercase()));
+
+    let npcs = dir.join("npc");
+    let re = Regex::new(r#"<npc\s+name="([^"]+)""#).context("npc pattern")?;
+    for entry in fs::read_dir(&npcs).with_context(|| format!("{}", npcs.display()))? {
+        let path = entry.context("npc entry")?.path();
+        if path.extension().is_some_and(|e| e == "xml") {
+            let xml = fs::read_to_string(&path).with_context(|| format!("{}", path.display()))?;
+            creatures.extend(re.captures_iter(&xml).map(|c| c[1].to_lowercase()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(forbidden: &[&str]) -> NamePolicy {
+        let cfg = Names {
+            reserved_prefixes: vec!["GM".to_owned(), "God".to_owned(), " ".to_owned()],
+            max_words: 3,
+            max_repeated: 2,
+            ..Default::default()
+        };
+        let creatures = ["rat", "the oracle"].map(str::to_owned).into();
+        with_words(
+            &cfg,
+            forbidden.iter().map(|w| (*w).to_owned()).collect(),
+            creatures,
+        )
+    }
+
+    #[test]
+    fn forbidden_words_match_anywhere() {
+        let policy = policy(&["Admin", "", "  "]);
+        assert!(matches!(
+            policy.check("Superadmin Joe"),
+            Err(ValidationError::NameForbidden { word }) if word == "admin"
+        ));
+        assert!(policy.check("Joe Black").is_ok());
+    }
+
+    #[test]
+    fn reserved_prefixes_match_the_start() {
+        let policy = policy(&[]);
+        for name in ["Gmbob", "Godlike Knight", "God Zezenia"] {
+            assert!(
+                matches!(
+                    policy.check(name),
+                    Err(ValidationError::NameReserved { .. })
+                ),
+                "{name}"
+            );
+        }
+        assert!(policy.check("Bob Gmail").is_ok());
+    }
+
+    #[test]
+    fn words_are_limited() {
+        let policy = policy(&[]);
+        assert!(policy.check("Abc Def Ghi").is_ok());
+        assert!(matches!(
+            policy.check("Abc Def Ghi Jkl"),
+            Err(ValidationError::NameTooManyWords { max: 3 })
+        ));
+    }
+
+    #[test]
+    fn repeated_letters_are_limited() {
+        let policy = policy(&[]);
+        assert!(policy.check("Aaron").is_ok());
+        assert!(matches!(
+            policy.check("Aaaron"),
+            Err(ValidationError::NameRepeatedLetters { max: 2 })
+        ));
+    }
+
+    #[test]
+    fn creature_names_are_rejected() {
+        let policy = policy(&[]);
+        assert!(matches!(
+            policy.check("Rat"),
+            Err(ValidationError::NameCreature)
+        ));
+        assert!(matches!(
+            policy.check("The Oracle"),
+            Err(ValidationError::NameCreature)
+        ));
+        assert!(policy.check("Rattler").is_ok());
+    }
+
+    #[test]
+    fn creatures_load_from_the_data_directory() {
+        let dir = std::env::temp_dir().join(format!("names-{}", std::process::id()));
+        fs::create_dir_all(dir.join("monster")).expect("monster dir");
+        fs::create_dir_all(dir.join("npc")).expect("npc dir");
+        fs::write(
+            dir.join("monster").join("monsters.xml"),
+            r#"<monsters><monster name="Dragon Lord" file="dragon lord.xml"/></monsters>"#,
+        )
+        .expect("monsters");
+        fs::write(
+            dir.join("npc").join("Sam.xml"),
+            r#"<npc name="Sam" script="sam.lua"></npc>"#,
+        )
+        .expect("npc");
+
+        let mut creatures = HashSet::new();
+        let loaded = load_creatures(&dir, &mut creatures);
+        fs::remove_dir_all(&dir).ok();
+        loaded.expect("creatures");
+        assert_eq!(creatures, ["dragon lord", "sam"].map(str::to_owned).into());
+    }
+}
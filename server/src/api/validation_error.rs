@@ -20,6 +20,23 @@ pub enum ValidationError {
     TooManyCharacters,
     CharacterAlreadyExists,
     CharacterNotExists,
+    CharacterOnline,
+    AccountCharacterOnline,
+    TransferDisabled,
+    TransferCooldown { until: u64 },
+    TransferLevel { min: u32, max: u32 },
+    TransferVocation,
+    TransferNameTaken,
+    CharacterInGuild,
+    CharacterHasHouse,
+    NotEnoughPremiumPoints,
+    NameForbidden { word: String },
+    NameReserved { prefix: String },
+    NameTooManyWords { max: usize },
+    NameRepeatedLetters { max: usize },
+    NameCreature,
+    PremiumWorld,
+    SampleNotExists,
 }
 
 #[derive(Object)]
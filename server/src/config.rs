@@ -1,8 +1,11 @@
 use std::{
     collections::HashMap,
     net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
 };
 
+use anyhow::{bail, Context};
+use chrono::NaiveDate;
 use figment::{
     providers::{Env, Format, Serialized, Toml},
     Figment,
@@ -11,6 +14,8 @@ use lazy_static::lazy_static;
 use serde::{Deserialize, Deserializer, Serialize};
 use tracing::metadata::LevelFilter;
 
+use crate::utils::experience;
+
 lazy_static! {
     static ref CONFIG: Config = new();
 }
@@ -38,16 +43,137 @@ pub struct Config {
     pub api: Api,
     pub jwt: Jwt,
     pub database: Database,
+    #[serde(deserialize_with = "deserialize_worlds")]
+    pub worlds: HashMap<u32, World>,
     #[serde(deserialize_with = "deserialize_str_map")]
-    pub worlds: HashMap<u32, String>,
+    pub towns: HashMap<u32, String>,
     pub account: Account,
     pub character: Character,
+    pub staff: Staff,
     pub highscores: Highscores,
     pub deaths: Deaths,
+    pub frags: Frags,
+    pub killstats: KillStatistics,
+    pub online: Online,
+    pub login: Login,
+    pub feed: Feed,
+    pub cache: Cache,
     pub validation: Validation,
     pub debug: Debug,
 }
 
+impl Config {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.killstats.refresh_interval == 0 {
+            bail!("killstats refresh interval must be at least 1 second");
+        }
+        validate_vocations(&self.character.new.vocations).context("new vocations")?;
+        for (id, world) in &self.worlds {
+            validate_vocations(&world.new.vocations)
+                .with_context(|| format!("world {id} new vocations"))?;
+            // The 8.x character list only carries IPv4 addresses
+            if self.login.server.enabled && world.ip.is_ipv6() {
+                bail!("world {id} needs an IPv4 address for the login server");
+            }
+            if let Some(new) = self.new_character(*id) {
+                if experience::for_level(new.level).is_none() {
+                    bail!("world {id} new level {} is too high", new.level);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the vocation group containing the vocation id
+    pub fn vocation_name(&self, vocation: u32) -> Option<&str> {
+        self.character
+            .vocations
+            .iter()
+            .find(|(_, ids)| ids.contains(&vocation))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Whether the world lets new characters pick the vocation or the one it is promoted from
+    pub fn vocation_allowed(&self, world: u32, vocation: u32) -> bool {
+        let Some(new) = self.new_character(world) else {
+            return false;
+        };
+        let group = self
+            .character
+            .vocations
+            .values()
+            .find(|ids| ids.contains(&vocation));
+        new.vocations.values().any(|v| match group {
+            Some(ids) => ids.contains(&v.vocation),
+            None => v.vocation == vocation,
+        })
+    }
+
+    /// Starting settings of the world with its overrides applied
+    pub fn new_character(&self, world: u32) -> Option<NewCharacterSettings<'_>> {
+        let world = &self.worlds.get(&world)?.new;
+        let new = &self.character.new;
+        Some(NewCharacterSettings {
+            level: world.level.unwrap_or(new.level),
+            health: world.health.unwrap_or(new.health),
+            mana: world.mana.unwrap_or(new.mana),
+            soul: world.soul.unwrap_or(new.soul),
+            cap: world.cap.unwrap_or(new.cap),
+            town: world.town.unwrap_or(new.town),
+            pos_x: world.pos_x.unwrap_or(new.pos_x),
+            pos_y: world.pos_y.unwrap_or(new.pos_y),
+            pos_z: world.pos_z.unwrap_or(new.pos_z),
+            vocations: if world.vocations.is_empty() {
+                &new.vocations
+            } else {
+                &world.vocations
+            },
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct World {
+    pub name: String,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub pvp_type: PvpType,
+    pub location: String,
+    pub created: Option<NaiveDate>,
+    pub premium_only: bool,
+    pub visible: bool,
+    pub new: NewCharacterOverride,
+    pub status: WorldStatus,
+    /// Game database of the world, the shared one when not set
+    pub database: Option<Database>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WorldStatus {
+    pub enabled: bool,
+    /// Status port, the login port on most servers
+    pub port: u16,
+    pub protocol: StatusProtocol,
+    pub timeout: u64,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusProtocol {
+    Xml,
+    Binary,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PvpType {
+    Pvp,
+    NoPvp,
+    PvpEnforced,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Api {
     pub name: String,
@@ -67,12 +193,16 @@ pub struct Jwt {
 }
 
 #[derive(Deserialize, Serialize)]
+#[serde(default)]
 pub struct Database {
     pub host: String,
     pub user: String,
     pub password: String,
     pub database: String,
     pub connections: u32,
+    /// Applies `migrations/worlds` on startup to the databases holding worlds. They create
+    /// the site's own tables, add `players.deletion_at` and record themselves in `_sqlx_migrations`
+    pub migrate: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -85,15 +215,54 @@ pub struct Account {
 #[serde(rename_all = "camelCase")]
 pub struct Character {
     pub insta_delete_below: u32,
+    pub deletion_time: u64,
+    pub purge_interval: u64,
     pub vocations: HashMap<String, Vec<u32>>,
     pub new: NewCharacter,
+    pub transfer: Transfer,
+    pub names: Names,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Names {
+    pub forbidden: Vec<String>,
+    pub forbidden_files: Vec<PathBuf>,
+    /// Matched against the start of the name, "GM" also rejects "Gmbob"
+    pub reserved_prefixes: Vec<String>,
+    pub max_words: usize,
+    pub max_repeated: usize,
+    pub data_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transfer {
+    pub enabled: bool,
+    pub cooldown: usize,
+    pub min_level: u32,
+    pub max_level: u32,
+    pub allow_guild: bool,
+    pub allow_house: bool,
+    pub price: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Staff {
+    /// Characters of this group and above are left out of highscores, online lists,
+    /// the feed, frags and snapshots, 0 lists everyone
+    pub group: u32,
 }
 
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Highscores {
     pub page_count: u32,
-    pub vocation_cache_time: usize,
+    /// Pages served without a world, each one merges every database up to its depth
+    pub max_pages: u32,
+    pub snapshot_interval: u64,
+    pub snapshot_retention: usize,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -102,9 +271,91 @@ pub struct Deaths {
     pub page_count: u32,
 }
 
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frags {
+    pub page_count: u32,
+    pub window: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KillStatistics {
+    pub refresh_interval: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Online {
+    pub sample_interval: u64,
+    pub history_retention: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Login {
+    pub enabled: bool,
+    pub path: String,
+    /// Race id of the boosted creature, 0 for none
+    pub boosted_creature: u32,
+    pub events: Vec<ClientEvent>,
+    pub server: LoginServer,
+}
+
+/// Classic TCP login protocol of 8.x clients
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginServer {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+    /// Protocol version accepted, 0 for any
+    pub client_version: u16,
+    /// Primes of the RSA private key, in decimal
+    pub rsa_p: String,
+    pub rsa_q: String,
+    pub motd_id: u32,
+    pub motd: String,
+    pub timeout: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientEvent {
+    pub name: String,
+    pub description: String,
+    pub start: u64,
+    pub end: u64,
+    #[serde(default = "default_event_color")]
+    pub color_light: String,
+    #[serde(default = "default_event_color")]
+    pub color_dark: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Feed {
+    pub poll_interval: u64,
+    pub max_subscribers: usize,
+    pub buffer: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cache {
+    pub capacity: usize,
+    pub highscores: u64,
+    pub deaths: u64,
+    pub frags: u64,
+    pub online: u64,
+    pub worlds: u64,
+    pub status: u64,
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NewCharacter {
+    pub level: u32,
     pub health: u32,
     pub mana: u32,
     pub soul: u32,
@@ -117,10 +368,70 @@ pub struct NewCharacter {
     pub vocations: HashMap<u32, NewVocation>,
 }
 
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCharacterOverride {
+    pub level: Option<u32>,
+    pub health: Option<u32>,
+    pub mana: Option<u32>,
+    pub soul: Option<u32>,
+    pub cap: Option<u32>,
+    pub town: Option<u32>,
+    pub pos_x: Option<u32>,
+    pub pos_y: Option<u32>,
+    pub pos_z: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_str_map")]
+    pub vocations: HashMap<u32, NewVocation>,
+}
+
+pub struct NewCharacterSettings<'a> {
+    pub level: u32,
+    pub health: u32,
+    pub mana: u32,
+    pub soul: u32,
+    pub cap: u32,
+    pub town: u32,
+    pub pos_x: u32,
+    pub pos_y: u32,
+    pub pos_z: u32,
+    pub vocations: &'a HashMap<u32, NewVocation>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct NewVocation {
     pub vocation: u32,
     pub looktype: u32,
+    #[serde(default)]
+    pub items: Vec<ItemTemplate>,
+    pub sample: Option<i32>,
+    #[serde(default, deserialize_with = "deserialize_str_map")]
+    pub samples: HashMap<u32, i32>,
+}
+
+impl NewVocation {
+    /// Sample player cloned into new characters of the world, if any
+    pub fn sample(&self, world: u32) -> Option<i32> {
+        self.samples.get(&world).copied().or(self.sample)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ItemTemplate {
+    pub slot: u32,
+    pub item: u32,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub contents: Vec<ContainerItem>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ContainerItem {
+    pub item: u32,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub contents: Vec<ContainerItem>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -158,6 +469,35 @@ impl From<Log> for LevelFilter {
     }
 }
 
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            ip: Ipv4Addr::new(127, 0, 0, 1).into(),
+            port: 7172,
+            pvp_type: PvpType::Pvp,
+            location: Default::default(),
+            created: None,
+            premium_only: false,
+            visible: true,
+            new: Default::default(),
+            status: Default::default(),
+            database: None,
+        }
+    }
+}
+
+impl Default for WorldStatus {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 7171,
+            protocol: StatusProtocol::Xml,
+            timeout: 5,
+        }
+    }
+}
+
 impl Default for Api {
     fn default() -> Self {
         Self {
@@ -189,6 +529,7 @@ impl Default for Database {
             password: Default::default(),
             database: "delirium".to_owned(),
             connections: 10,
+            migrate: false,
         }
     }
 }
@@ -203,17 +544,58 @@ impl Default for Character {
     fn default() -> Self {
         Self {
             insta_delete_below: 10,
+            deletion_time: 7 * 24 * 60 * 60,
+            purge_interval: 60 * 60,
             vocations: HashMap::new(),
             new: Default::default(),
+            transfer: Default::default(),
+            names: Default::default(),
         }
     }
 }
 
+impl Default for Names {
+    fn default() -> Self {
+        Self {
+            forbidden: Vec::new(),
+            forbidden_files: Vec::new(),
+            reserved_prefixes: ["GM", "CM", "God", "Gamemaster", "Tutor"]
+                .map(str::to_owned)
+                .into(),
+            max_words: 3,
+            max_repeated: 2,
+            data_dir: None,
+        }
+    }
+}
+
+impl Default for Transfer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cooldown: 30 * 24 * 60 * 60,
+            min_level: 0,
+            max_level: 0,
+            allow_guild: false,
+            allow_house: false,
+            price: 0,
+        }
+    }
+}
+
+impl Default for Staff {
+    fn default() -> Self {
+        Self { group: 3 }
+    }
+}
+
 impl Default for Highscores {
     fn default() -> Self {
         Self {
             page_count: 20,
-            vocation_cache_time: 0,
+            max_pages: 50,
+            snapshot_interval: 6 * 60 * 60,
+            snapshot_retention: 90 * 24 * 60 * 60,
         }
     }
 }
@@ -224,9 +606,88 @@ impl Default for Deaths {
     }
 }
 
+impl Default for Frags {
+    fn default() -> Self {
+        Self {
+            page_count: 20,
+            window: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for KillStatistics {
+    fn default() -> Self {
+        Self {
+            refresh_interval: 15 * 60,
+        }
+    }
+}
+
+impl Default for Online {
+    fn default() -> Self {
+        Self {
+            sample_interval: 5 * 60,
+            history_retention: 0,
+        }
+    }
+}
+
+impl Default for Login {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/login.php".to_owned(),
+            boosted_creature: 0,
+            events: Vec::new(),
+            server: Default::default(),
+        }
+    }
+}
+
+impl Default for LoginServer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: Ipv4Addr::new(0, 0, 0, 0).into(),
+            port: 7171,
+            client_version: 860,
+            rsa_p: Default::default(),
+            rsa_q: Default::default(),
+            motd_id: 1,
+            motd: "Welcome!".to_owned(),
+            timeout: 10,
+        }
+    }
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            poll_interval: 3,
+            max_subscribers: 256,
+            buffer: 128,
+        }
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            highscores: 0,
+            deaths: 0,
+            frags: 0,
+            online: 0,
+            worlds: 0,
+            status: 30,
+        }
+    }
+}
+
 impl Default for NewCharacter {
     fn default() -> Self {
         Self {
+            level: 1,
             health: 200,
             mana: 0,
             soul: 100,
@@ -245,6 +706,9 @@ impl Default for NewVocation {
         Self {
             vocation: 0,
             looktype: 100,
+            items: Vec::new(),
+            sample: None,
+            samples: HashMap::new(),
         }
     }
 }
@@ -282,3 +746,121 @@ where
     }
     Ok(ret)
 }
+
+/// Worlds as tables, or as plain names like `worlds = { 1 = "Antica" }`
+fn deserialize_worlds<'de, D>(deserializer: D) -> Result<HashMap<u32, World>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Name(String),
+        World(World),
+    }
+
+    let worlds: HashMap<u32, Entry> = deserialize_str_map(deserializer)?;
+    Ok(worlds
+        .into_iter()
+        .map(|(id, entry)| {
+            let world = match entry {
+                Entry::Name(name) => World {
+                    name,
+                    ..Default::default()
+                },
+                Entry::World(world) => world,
+            };
+            (id, world)
+        })
+        .collect())
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+fn default_event_color() -> String {
+    "#64162b".to_owned()
+}
+
+fn validate_vocations(vocations: &HashMap<u32, NewVocation>) -> anyhow::Result<()> {
+    for (id, voc) in vocations {
+        if !voc.items.is_empty() && (voc.sample.is_some() || !voc.samples.is_empty()) {
+            bail!("vocation {id} has both items and sample characters");
+        }
+        validate_items(&voc.items).with_context(|| format!("vocation {id} items"))?;
+    }
+    Ok(())
+}
+
+fn validate_items(items: &[ItemTemplate]) -> anyhow::Result<()> {
+    let mut slots = Vec::new();
+    for item in items {
+        if !(1..=10).contains(&item.slot) {
+            bail!("invalid slot {}", item.slot);
+        }
+        if slots.contains(&item.slot) {
+            bail!("duplicate slot {}", item.slot);
+        }
+        slots.push(item.slot);
+        validate_item(item.item, item.count, &item.contents)
+            .with_context(|| format!("slot {}", item.slot))?;
+    }
+    Ok(())
+}
+
+fn validate_item(item: u32, count: u32, contents: &[ContainerItem]) -> anyhow::Result<()> {
+    if item == 0 {
+        bail!("missing item id");
+    }
+    if !(1..=100).contains(&count) {
+        bail!("invalid count {count} of item {item}");
+    }
+    for content in contents {
+        validate_item(content.item, content.count, &content.contents)
+            .with_context(|| format!("container {item}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::string(toml))
+            .extract::<Config>()
+            .expect("config")
+    }
+
+    fn worlds(toml: &str) -> HashMap<u32, World> {
+        config(toml).worlds
+    }
+
+    #[test]
+    fn worlds_accept_plain_names() {
+        let worlds = worlds("[worlds]\n1 = \"Antica\"\n");
+        assert_eq!(worlds[&1].name, "Antica");
+        assert_eq!(worlds[&1].port, 7172);
+        assert!(worlds[&1].visible);
+    }
+
+    #[test]
+    fn worlds_accept_tables() {
+        let worlds = worlds(
+            "[worlds.1]\nname = \"Antica\"\n\n[worlds.2]\nname = \"Secura\"\npremiumOnly = true\n",
+        );
+        assert_eq!(worlds[&1].name, "Antica");
+        assert_eq!(worlds[&2].name, "Secura");
+        assert!(worlds[&2].premium_only);
+    }
+
+    #[test]
+    fn login_server_rejects_ipv6_worlds() {
+        let worlds = "[worlds.1]\nname = \"Antica\"\nip = \"::1\"\n";
+        assert!(config(worlds).validate().is_ok());
+        let login = "[login.server]\nenabled = true\n";
+        assert!(config(&format!("{login}{worlds}")).validate().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
 use dotenv::dotenv;
 use poem::{listener::TcpListener, Server};
@@ -13,6 +15,7 @@ mod services;
 async fn main() -> Result<()> {
     dotenv().ok();
     let cfg = config::get();
+    cfg.validate().context("config")?;
 
     tracing_subscriber::fmt()
         .with_max_level(cfg.debug.log)
@@ -21,18 +24,31 @@ async fn main() -> Result<()> {
 
     trace!("hi");
 
-    let pool = sqlx::mysql::MySqlPoolOptions::new()
-        .max_connections(cfg.database.connections)
-        .connect(&format!(
-            "mysql://{}:{}@{}/{}",
-            cfg.database.user, cfg.database.password, cfg.database.host, cfg.database.database
-        ))
-        .await
-        .context("database connection")?;
+    let databases = Arc::new(services::databases::connect().await?);
+    databases.validate_samples().await.context("config")?;
     let jwt = services::jwt::new();
+    let names = api::name_policy::new().context("name policy")?;
+    let cache = Arc::new(services::cache::new());
+    for pool in databases.all() {
+        services::deletion::spawn(pool, &cache);
+        services::snapshots::spawn(pool);
+    }
+    services::online::spawn(&databases);
+    let kill_statistics = services::kill_statistics::spawn(&databases);
+    let feed = services::feed::spawn(&databases);
+    services::login_server::spawn(&databases)
+        .await
+        .context("login server")?;
 
     Server::new(TcpListener::bind((cfg.api.address, cfg.api.port)))
-        .run(api::routes(&pool, jwt))
+        .run(api::routes(
+            &databases,
+            jwt,
+            names,
+            &kill_statistics,
+            &feed,
+            &cache,
+        ))
         .await
         .context("server start")
 }
@@ -0,0 +1,232 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tokio::{sync::OnceCell, time::Instant};
+use tracing::debug;
+
+use crate::config;
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+/// Prefixes of the entries built from character rows
+const CHARACTER_PREFIXES: [&str; 5] =
+    ["highscores/", "deaths/", "frags/", "online/", "worlds/list"];
+
+pub struct Cache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Keys by last use, least recently used first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+struct Entry {
+    cell: Arc<OnceCell<Loaded>>,
+    used: u64,
+}
+
+struct Loaded {
+    value: Value,
+    expires: Instant,
+}
+
+pub fn new() -> Cache {
+    with_capacity(config::get().cache.capacity)
+}
+
+fn with_capacity(capacity: usize) -> Cache {
+    Cache {
+        capacity,
+        inner: Mutex::new(Inner {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }),
+    }
+}
+
+impl Cache {
+    /// Cached value of the key, concurrent misses share a single load.
+    /// The TTL counts from the moment the load finished
+    pub async fn get<T, F, Fut>(&self, key: String, ttl: u64, load: F) -> Result<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if ttl == 0 || self.capacity == 0 {
+            return load().await;
+        }
+        let cell = self.cell(key);
+        let loaded = cell
+            .get_or_try_init(|| async {
+                let value = load().await?;
+                Ok::<_, anyhow::Error>(Loaded {
+                    value: Arc::new(value),
+                    expires: Instant::now() + Duration::from_secs(ttl),
+                })
+            })
+            .await?;
+        loaded
+            .value
+            .downcast_ref::<T>()
+            .context("cache type")
+            .cloned()
+    }
+
+    /// Drops every entry whose key starts with the prefix
+    pub fn invalidate(&self, prefix: &str) {
+        let mut inner = self.inner.lock().expect("lock");
+        let Inner { entries, order, .. } = &mut *inner;
+        entries.retain(|k, e| {
+            let keep = !k.starts_with(prefix);
+            if !keep {
+                order.remove(&e.used);
+            }
+            keep
+        });
+        debug!("Invalidated '{prefix}' cache");
+    }
+
+    /// Drops the entries that may show a created, deleted or moved character
+    pub fn invalidate_characters(&self) {
+        for prefix in CHARACTER_PREFIXES {
+            self.invalidate(prefix);
+        }
+    }
+
+    fn cell(&self, key: String) -> Arc<OnceCell<Loaded>> {
+        let mut inner = self.inner.lock().expect("lock");
+        inner.tick += 1;
+        let tick = inner.tick;
+        let now = Instant::now();
+        let Inner { entries, order, .. } = &mut *inner;
+        if let Some(entry) = entries.get_mut(&key) {
+            // Entries still loading are shared, finished ones until they expire
+            if !entry.cell.get().is_some_and(|l| l.expires <= now) {
+                order.remove(&entry.used);
+                order.insert(tick, key);
+                entry.used = tick;
+                return entry.cell.clone();
+            }
+            order.remove(&entry.used);
+            entries.remove(&key);
+        } else if entries.len() >= self.capacity {
+            if let Some((_, lru)) = order.pop_first() {
+                entries.remove(&lru);
+            }
+        }
+        let cell = Arc::new(OnceCell::new());
+        order.insert(tick, key.clone());
+        entries.insert(
+            key,
+            Entry {
+                cell: cell.clone(),
+                used: tick,
+            },
+        );
+        cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    async fn load(cache: &Cache, key: &str, ttl: u64, loads: &AtomicUsize) -> u32 {
+        cache
+            .get(key.to_owned(), ttl, || async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(7)
+            })
+            .await
+            .expect("load")
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_expire_after_ttl() {
+        let cache = with_capacity(8);
+        let loads = AtomicUsize::new(0);
+        load(&cache, "a", 10, &loads).await;
+        tokio::time::advance(Duration::from_secs(9)).await;
+        load(&cache, "a", 10, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        tokio::time::advance(Duration::from_secs(2)).await;
+        load(&cache, "a", 10, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ttl_starts_when_load_finishes() {
+        let cache = with_capacity(8);
+        let loads = AtomicUsize::new(0);
+        let slow = cache
+            .get("a".to_owned(), 10, || async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_secs(8)).await;
+                Ok(7u32)
+            })
+            .await
+            .expect("load");
+        assert_eq!(slow, 7);
+        tokio::time::advance(Duration::from_secs(5)).await;
+        load(&cache, "a", 10, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_is_evicted() {
+        let cache = with_capacity(2);
+        let loads = AtomicUsize::new(0);
+        load(&cache, "a", 60, &loads).await;
+        load(&cache, "b", 60, &loads).await;
+        load(&cache, "a", 60, &loads).await;
+        load(&cache, "c", 60, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        load(&cache, "a", 60, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        load(&cache, "b", 60, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_misses_share_one_load() {
+        let cache = with_capacity(8);
+        let loads = AtomicUsize::new(0);
+        let slow = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(7u32)
+        };
+        let (a, b) = tokio::join!(
+            cache.get("a".to_owned(), 60, slow),
+            cache.get("a".to_owned(), 60, slow)
+        );
+        assert_eq!((a.expect("a"), b.expect("b")), (7, 7));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn invalidation_drops_prefixed_entries() {
+        let cache = with_capacity(8);
+        let loads = AtomicUsize::new(0);
+        load(&cache, "deaths/1", 60, &loads).await;
+        load(&cache, "worlds/status/1", 60, &loads).await;
+        cache.invalidate_characters();
+        load(&cache, "deaths/1", 60, &loads).await;
+        load(&cache, "worlds/status/1", 60, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+}
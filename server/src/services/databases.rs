@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use sqlx::{migrate::Migrator, mysql::MySqlPoolOptions, query, MySql, Pool};
+use tracing::info;
+
+use crate::config::{self, Database};
+
+/// Shared accounts database and the game databases of the worlds. Character ids are
+/// only unique within a database, characters are always looked up through their world
+pub struct Databases {
+    /// Accounts pool first, worlds with their own database after
+    pools: Vec<Pool<MySql>>,
+    /// Pool of every configured world
+    worlds: HashMap<u32, usize>,
+    /// Pools holding at least one world
+    characters: Vec<usize>,
+}
+
+/// Connects every configured database, worlds sharing settings share a pool.
+/// The databases holding worlds get `migrations/worlds`
+pub async fn connect() -> Result<Databases> {
+    let cfg = config::get();
+    let mut configs = vec![&cfg.database];
+    let mut urls = vec![url(&cfg.database)];
+    let mut pools = vec![pool(&cfg.database).await.context("accounts database")?];
+    let mut worlds = HashMap::new();
+    for (&id, world) in &cfg.worlds {
+        let Some(database) = &world.database else {
+            worlds.insert(id, 0);
+            continue;
+        };
+        let url = url(database);
+        let index = match urls.iter().position(|u| *u == url) {
+            Some(index) => index,
+            None => {
+                pools.push(
+                    pool(database)
+                        .await
+                        .with_context(|| format!("world {id} database"))?,
+                );
+                configs.push(database);
+                urls.push(url);
+                pools.len() - 1
+            }
+        };
+        worlds.insert(id, index);
+    }
+    let mut characters = worlds.values().copied().collect::<Vec<_>>();
+    characters.sort_unstable();
+    characters.dedup();
+
+    for &index in &characters {
+        migrate(
+            sqlx::migrate!("./migrations/worlds"),
+            &pools[index],
+            configs[index],
+        )
+        .await
+        .with_context(|| format!("database '{}'", configs[index].database))?;
+    }
+    if pools.len() > 1 {
+        info!("Connected to {} databases", pools.len());
+    }
+    Ok(Databases {
+        pools,
+        worlds,
+        characters,
+    })
+}
+
+fn url(cfg: &Database) -> String {
+    format!(
+        "mysql://{}:{}@{}/{}",
+        cfg.user, cfg.password, cfg.host, cfg.database
+    )
+}
+
+async fn pool(cfg: &Database) -> Result<Pool<MySql>> {
+    MySqlPoolOptions::new()
+        .max_connections(cfg.connections)
+        .connect(&url(cfg))
+        .await
+        .context("database connection")
+}
+
+async fn migrate(mut migrator: Migrator, pool: &Pool<MySql>, cfg: &Database) -> Result<()> {
+    if !cfg.migrate {
+        info!(
+            "Migrations of '{}' are disabled, apply them manually or set migrate",
+            cfg.database
+        );
+        return Ok(());
+    }
+    // Versions recorded by earlier builds, such as the dropped account sessions, are skipped
+    migrator
+        .set_ignore_missing(true)
+        .run(pool)
+        .await
+        .context("database migration")
+}
+
+impl Databases {
+    /// Accounts, and the characters of worlds without their own database
+    pub fn accounts(&self) -> &Pool<MySql> {
+        &self.pools[0]
+    }
+
+    /// Pool holding the characters of the world
+    pub fn world(&self, id: u32) -> Result<&Pool<MySql>> {
+        let index = self
+            .worlds
+            .get(&id)
+            .with_context(|| format!("unknown world {id}"))?;
+        Ok(&self.pools[*index])
+    }
+
+    /// Every distinct pool holding characters, for views spanning all worlds
+    pub fn all(&self) -> Vec<&Pool<MySql>> {
+        self.characters.iter().map(|&i| &self.pools[i]).collect()
+    }
+
+    /// Configured worlds keeping their characters in the pool
+    pub fn worlds_in(&self, pool: &Pool<MySql>) -> Vec<u32> {
+        self.worlds
+            .iter()
+            .filter(|(_, &index)| std::ptr::eq(&self.pools[index], pool))
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// Whether both worlds keep their characters in the same database
+    pub fn shared(&self, a: u32, b: u32) -> bool {
+        matches!((self.worlds.get(&a), self.worlds.get(&b)), (Some(a), Some(b)) if a == b)
+    }
+
+    /// No world configured and a pool that never connects, for handlers that must answer
+    /// without touching the database
+    #[cfg(test)]
+    pub fn unconnected() -> Self {
+        Self {
+            pools: vec![MySqlPoolOptions::new()
+                .connect_lazy("mysql://localhost/delirium")
+                .expect("pool")],
+            worlds: HashMap::new(),
+            characters: Vec::new(),
+        }
+    }
+
+    /// Checks that the sample characters new characters are cloned from exist
+    pub async fn validate_samples(&self) -> Result<()> {
+        let cfg = config::get();
+        for &world in cfg.worlds.keys() {
+            let Some(new) = cfg.new_character(world) else {
+                continue;
+            };
+            for (id, vocation) in new.vocations {
+                let Some(sample) = vocation.sample(world) else {
+                    continue;
+                };
+                if query!("SELECT id FROM players WHERE id=?", sample)
+                    .fetch_optional(self.world(world)?)
+                    .await
+                    .context("sample character")?
+                    .is_none()
+                {
+                    bail!("world {world} vocation {id} sample character {sample} does not exist");
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use sqlx::{query, MySql, Pool, Transaction};
+use tracing::{error, info};
+
+use crate::{config, services::cache::Cache, utils::time};
+
+const DEPENDENT_TABLES: [&str; 6] = [
+    "player_items",
+    "player_depotitems",
+    "player_storage",
+    "player_spells",
+    "player_viplist",
+    "player_killers",
+];
+
+/// Killer tables keyed by `killers.id`, emptied before the killers of the deaths
+const KILL_TABLES: [&str; 2] = ["player_killers", "environment_killers"];
+
+pub fn spawn(db: &Pool<MySql>, cache: &Arc<Cache>) {
+    let period = config::get().character.purge_interval;
+    if period == 0 {
+        return;
+    }
+    let db = db.clone();
+    let cache = cache.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(period));
+        loop {
+            interval.tick().await;
+            match purge_expired(&db).await {
+                Ok(0) => {}
+                Ok(_) => cache.invalidate_characters(),
+                Err(err) => error!("Character purge failed: {:?}", err),
+            }
+        }
+    });
+}
+
+pub async fn purge(tx: &mut Transaction<'_, MySql>, id: i32) -> Result<()> {
+    for table in DEPENDENT_TABLES {
+        sqlx::query(&format!("DELETE FROM {table} WHERE player_id = ?"))
+            .bind(id)
+            .execute(&mut **tx)
+            .await
+            .context(table)?;
+    }
+    for table in KILL_TABLES {
+        sqlx::query(&format!(
+            "DELETE FROM {table} WHERE kill_id IN (SELECT k.id FROM killers AS k INNER JOIN player_deaths AS pd ON pd.id = k.death_id WHERE pd.player_id = ?)"
+        ))
+        .bind(id)
+        .execute(&mut **tx)
+        .await
+        .context(table)?;
+    }
+    query!(
+        "DELETE FROM killers WHERE death_id IN (SELECT id FROM player_deaths WHERE player_id = ?)",
+        id
+    )
+    .execute(&mut **tx)
+    .await
+    .context("killers")?;
+    query!("DELETE FROM player_deaths WHERE player_id = ?", id)
+        .execute(&mut **tx)
+        .await
+        .context("player_deaths")?;
+    query!("DELETE FROM players WHERE id=?", id)
+        .execute(&mut **tx)
+        .await
+        .context("delete player")?;
+    Ok(())
+}
+
+/// Number of characters purged
+async fn purge_expired(db: &Pool<MySql>) -> Result<usize> {
+    let records = query!(
+        "SELECT id FROM players WHERE deleted AND deletion_at > 0 AND deletion_at <= ?",
+        time::timestamp()
+    )
+    .fetch_all(db)
+    .await
+    .context("expired players")?;
+    let count = records.len();
+    for record in records {
+        let mut tx = db.begin().await.context("transaction")?;
+        purge(&mut tx, record.id).await?;
+        tx.commit().await.context("commit")?;
+        info!("Purged character '{}'", record.id);
+    }
+    Ok(count)
+}
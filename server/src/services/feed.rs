@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures_util::{stream, Stream};
+use sqlx::{query, query_scalar, MySql, Pool};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::MissedTickBehavior,
+};
+use tracing::{debug, error};
+
+use crate::{config, services::databases::Databases};
+
+const DEATHS_PER_POLL: u32 = 100;
+
+/// Single poller fanning out new deaths and login changes to every subscriber
+pub struct Feed {
+    sender: broadcast::Sender<Event>,
+    subscribers: AtomicUsize,
+}
+
+#[derive(Clone)]
+pub enum Event {
+    Death {
+        world: u32,
+        id: i32,
+        player_id: i32,
+        name: String,
+        level: u32,
+        date: u64,
+    },
+    Login {
+        world: u32,
+        id: i32,
+        name: String,
+        level: u32,
+        vocation: u32,
+    },
+    Logout {
+        world: u32,
+        id: i32,
+        name: String,
+    },
+}
+
+impl Event {
+    pub fn world(&self) -> u32 {
+        match self {
+            Event::Death { world, .. } | Event::Login { world, .. } | Event::Logout { world, .. } => {
+                *world
+            }
+        }
+    }
+}
+
+/// Counted receiver, releases its slot when dropped
+pub struct Subscription {
+    feed: Arc<Feed>,
+    receiver: broadcast::Receiver<Event>,
+}
+
+struct State {
+    last_death: i32,
+    online: HashMap<i32, Player>,
+}
+
+struct Player {
+    world: u32,
+    name: String,
+    level: u32,
+    vocation: u32,
+}
+
+pub fn spawn(databases: &Databases) -> Arc<Feed> {
+    let cfg = &config::get().feed;
+    let (sender, _) = broadcast::channel(cfg.buffer.max(1));
+    let feed = Arc::new(Feed {
+        sender,
+        subscribers: AtomicUsize::new(0),
+    });
+    if cfg.poll_interval == 0 {
+        return feed;
+    }
+    let period = cfg.poll_interval;
+    // One poller per database, all publishing into the same channel
+    for db in databases.all() {
+        let db = db.clone();
+        let poller = feed.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(period));
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut state = None;
+            loop {
+                interval.tick().await;
+                // Nobody listening, start over silently once someone subscribes
+                if poller.sender.receiver_count() == 0 {
+                    state = None;
+                    continue;
+                }
+                // A failed poll keeps the last good state, the next one catches up from it
+                match poller.poll(&db, state.as_ref()).await {
+                    Ok(next) => state = Some(next),
+                    Err(err) => error!("Feed poll failed: {:?}", err),
+                }
+            }
+        });
+    }
+    feed
+}
+
+impl Feed {
+    /// `None` once `max_subscribers` is reached
+    pub fn subscribe(self: &Arc<Self>) -> Option<Subscription> {
+        let max = config::get().feed.max_subscribers;
+        self.subscribers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Subscription {
+            feed: self.clone(),
+            receiver: self.sender.subscribe(),
+        })
+    }
+
+    /// Publishes the changes since the state, nothing when a query fails
+    async fn poll(&self, db: &Pool<MySql>, state: Option<&State>) -> Result<State> {
+        let staff = config::get().staff.group;
+        let online = query!(
+            r#"SELECT id, name, level AS "level: u32", vocation AS "vocation: u32", world_id AS "world_id: u32" FROM players WHERE online = 1 AND (? = 0 OR group_id < ?)"#,
+            staff,
+            staff
+        )
+        .fetch_all(db)
+        .await
+        .context("feed online")?
+        .into_iter()
+        .map(|row| {
+            (
+                row.id,
+                Player {
+                    world: row.world_id,
+                    name: row.name,
+                    level: row.level,
+                    vocation: row.vocation,
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+        let Some(state) = state else {
+            let last_death = query_scalar!(
+                r#"SELECT CAST(COALESCE(MAX(id), 0) AS SIGNED) AS "id!: i32" FROM player_deaths"#
+            )
+            .fetch_one(db)
+            .await
+            .context("feed last death")?;
+            return Ok(State { last_death, online });
+        };
+
+        let deaths = query!(
+            r#"SELECT pd.id, p.id AS player_id, p.name, pd.level AS "level: u32", pd.date AS "date: u64", p.world_id AS "world_id: u32" FROM player_deaths AS pd INNER JOIN players AS p ON p.id = pd.player_id WHERE pd.id > ? ORDER BY pd.id LIMIT ?"#,
+            state.last_death,
+            DEATHS_PER_POLL
+        )
+        .fetch_all(db)
+        .await
+        .context("feed deaths")?;
+        let mut last_death = state.last_death;
+        for death in deaths {
+            last_death = death.id;
+            self.publish(Event::Death {
+                world: death.world_id,
+                id: death.id,
+                player_id: death.player_id,
+                name: death.name,
+                level: death.level,
+                date: death.date,
+            });
+        }
+
+        for (id, player) in &online {
+            if !state.online.contains_key(id) {
+                self.publish(Event::Login {
+                    world: player.world,
+                    id: *id,
+                    name: player.name.clone(),
+                    level: player.level,
+                    vocation: player.vocation,
+                });
+            }
+        }
+        for (id, player) in &state.online {
+            if !online.contains_key(id) {
+                self.publish(Event::Logout {
+                    world: player.world,
+                    id: *id,
+                    name: player.name.clone(),
+                });
+            }
+        }
+
+        Ok(State { last_death, online })
+    }
+
+    fn publish(&self, event: Event) {
+        // Only fails without receivers
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Subscription {
+    /// Events of one world, ends when the subscriber falls behind the buffer
+    pub fn events(self, world: u32) -> impl Stream<Item = Event> + Send + 'static {
+        stream::unfold(self, move |mut subscription| async move {
+            loop {
+                match subscription.receiver.recv().await {
+                    Ok(event) if event.world() == world => return Some((event, subscription)),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Feed subscriber dropped after lagging {} events", skipped);
+                        return None;
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.feed.subscribers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use poem_openapi::Enum;
+use sqlx::{FromRow, MySql, Pool, QueryBuilder, Row};
+
+use crate::{config, services::databases::Databases};
+
+#[derive(Debug, Enum, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Category {
+    Level,
+    Fist,
+    Club,
+    Sword,
+    Axe,
+    Distance,
+    Shielding,
+    Fishing,
+    Magic,
+}
+
+impl Category {
+    pub const ALL: [Category; 9] = [
+        Category::Level,
+        Category::Fist,
+        Category::Club,
+        Category::Sword,
+        Category::Axe,
+        Category::Distance,
+        Category::Shielding,
+        Category::Fishing,
+        Category::Magic,
+    ];
+
+    /// Ranked column and the column breaking its ties
+    pub fn columns(self) -> (&'static str, &'static str) {
+        match self {
+            Category::Level => ("level", "experience"),
+            Category::Fist => ("skill_fist", "skill_fist_tries"),
+            Category::Club => ("skill_club", "skill_club_tries"),
+            Category::Sword => ("skill_sword", "skill_sword_tries"),
+            Category::Axe => ("skill_axe", "skill_axe_tries"),
+            Category::Distance => ("skill_dist", "skill_dist_tries"),
+            Category::Shielding => ("skill_shielding", "skill_shielding_tries"),
+            Category::Fishing => ("skill_fishing", "skill_fishing_tries"),
+            Category::Magic => ("maglevel", "manaspent"),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Filter {
+    pub world: Option<u32>,
+    pub vocations: Option<&'static [u32]>,
+}
+
+impl Filter {
+    /// Filter by configured vocation group name, `None` if the group is unknown
+    pub fn new(world: Option<u32>, vocation: Option<&str>) -> Option<Self> {
+        let vocations = match vocation {
+            Some(name) => Some(config::get().character.vocations.get(name)?.as_slice()),
+            None => None,
+        };
+        Some(Self { world, vocations })
+    }
+
+    fn push(&self, qb: &mut QueryBuilder<'_, MySql>) {
+        qb.push(" WHERE 1 = 1");
+        let staff = config::get().staff.group;
+        if staff > 0 {
+            qb.push(" AND group_id < ").push_bind(staff);
+        }
+        if let Some(world) = self.world {
+            qb.push(" AND world_id = ").push_bind(world);
+        }
+        if let Some(vocations) = self.vocations {
+            if vocations.is_empty() {
+                qb.push(" AND FALSE");
+            } else {
+                qb.push(" AND vocation IN (");
+                let mut separated = qb.separated(", ");
+                for vocation in vocations {
+                    separated.push_bind(*vocation);
+                }
+                qb.push(")");
+            }
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct Entry {
+    pub id: i32,
+    pub name: String,
+    pub vocation: u32,
+    pub world_id: u32,
+    pub value: u64,
+    pub points: u64,
+    #[sqlx(skip)]
+    pub rank: u64,
+}
+
+pub struct Page {
+    pub entries: Vec<Entry>,
+    pub total: u64,
+}
+
+/// Ranked page of the category, tied characters share the rank.
+/// Without a world the pages of every database are merged
+pub async fn page(
+    databases: &Databases,
+    category: Category,
+    filter: Filter,
+    skip: u32,
+    count: u32,
+) -> Result<Page> {
+    let pools = match filter.world {
+        Some(world) => vec![databases.world(world)?],
+        None => databases.all(),
+    };
+    let merged = pools.len() > 1;
+    let mut page = Vec::new();
+    let mut total = 0;
+    for &db in &pools {
+        page.extend(if merged {
+            entries(db, category, filter, 0, skip + count).await?
+        } else {
+            entries(db, category, filter, skip, count).await?
+        });
+        let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM players");
+        filter.push(&mut qb);
+        let found: i64 = qb
+            .build_query_scalar()
+            .fetch_one(db)
+            .await
+            .context("highscores total")?;
+        total += found as u64;
+    }
+    if merged {
+        page.sort_by(|a, b| {
+            (b.value, b.points)
+                .cmp(&(a.value, a.points))
+                .then(a.id.cmp(&b.id))
+        });
+        page.drain(..page.len().min(skip as usize));
+        page.truncate(count as usize);
+    }
+
+    let mut prev: Option<(u64, u64, u64)> = None;
+    for (i, e) in page.iter_mut().enumerate() {
+        e.rank = match prev {
+            Some((value, points, rank)) if value == e.value && points == e.points => rank,
+            Some(_) => skip as u64 + i as u64 + 1,
+            None if skip == 0 => 1,
+            None => {
+                let mut above = 0;
+                for &db in &pools {
+                    above += ahead(db, category, filter, e.value, e.points).await?;
+                }
+                above + 1
+            }
+        };
+        prev = Some((e.value, e.points, e.rank));
+    }
+    Ok(Page {
+        entries: page,
+        total,
+    })
+}
+
+pub async fn entries(
+    db: &Pool<MySql>,
+    category: Category,
+    filter: Filter,
+    skip: u32,
+    count: u32,
+) -> Result<Vec<Entry>> {
+    let (value, points) = category.columns();
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT id, name, vocation, world_id, CAST({value} AS UNSIGNED) AS value, CAST({points} AS UNSIGNED) AS points FROM players"
+    ));
+    filter.push(&mut qb);
+    qb.push(format!(" ORDER BY {value} DESC, {points} DESC, id ASC LIMIT "))
+        .push_bind(skip)
+        .push(", ")
+        .push_bind(count);
+    qb.build_query_as::<Entry>()
+        .fetch_all(db)
+        .await
+        .with_context(|| format!("{category:?} highscores"))
+}
+
+/// Number of characters ranked strictly above the given score
+pub async fn ahead(
+    db: &Pool<MySql>,
+    category: Category,
+    filter: Filter,
+    value: u64,
+    points: u64,
+) -> Result<u64> {
+    let (value_column, points_column) = category.columns();
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM players");
+    filter.push(&mut qb);
+    qb.push(format!(" AND ({value_column} > "))
+        .push_bind(value)
+        .push(format!(" OR ({value_column} = "))
+        .push_bind(value)
+        .push(format!(" AND {points_column} > "))
+        .push_bind(points)
+        .push("))");
+    let ahead: i64 = qb
+        .build_query_scalar()
+        .fetch_one(db)
+        .await
+        .with_context(|| format!("{category:?} rank"))?;
+    Ok(ahead as u64)
+}
+
+/// Rank of the character in every category, counted in a single scan
+pub async fn ranks(db: &Pool<MySql>, filter: Filter, id: i32) -> Result<Vec<(Category, u64)>> {
+    let mut counts = Vec::new();
+    let mut scores = Vec::new();
+    for (i, category) in Category::ALL.iter().enumerate() {
+        let (value, points) = category.columns();
+        counts.push(format!(
+            "CAST(COALESCE(SUM({value} > v{i} OR ({value} = v{i} AND {points} > p{i})), 0) AS UNSIGNED)"
+        ));
+        scores.push(format!("{value} AS v{i}, {points} AS p{i}"));
+    }
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {} FROM players CROSS JOIN (SELECT {} FROM players WHERE id = ",
+        counts.join(", "),
+        scores.join(", ")
+    ));
+    qb.push_bind(id).push(") AS c");
+    filter.push(&mut qb);
+    let row = qb.build().fetch_one(db).await.context("ranks")?;
+    Category::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, &category)| {
+            let ahead: u64 = row.try_get(i).context("rank")?;
+            Ok((category, ahead + 1))
+        })
+        .collect()
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use sqlx::query;
+use tracing::{debug, error};
+
+use crate::{
+    config,
+    services::databases::Databases,
+    utils::{article, time},
+};
+
+const DAY: usize = 24 * 60 * 60;
+
+pub struct Service {
+    summary: RwLock<HashMap<u32, Vec<Entry>>>,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    pub name: String,
+    pub last_day: u64,
+    pub last_week: u64,
+}
+
+pub fn spawn(databases: &Arc<Databases>) -> Arc<Service> {
+    let service = Arc::new(Service {
+        summary: RwLock::new(HashMap::new()),
+    });
+    // Never 0, see Config::validate
+    let period = config::get().killstats.refresh_interval;
+    let databases = databases.clone();
+    let refreshed = service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(period));
+        loop {
+            interval.tick().await;
+            if let Err(err) = refreshed.refresh(&databases).await {
+                error!("Kill statistics refresh failed: {:?}", err);
+            }
+        }
+    });
+    service
+}
+
+impl Service {
+    pub fn world(&self, world: u32) -> Vec<Entry> {
+        self.summary
+            .read()
+            .expect("lock")
+            .get(&world)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn refresh(&self, databases: &Databases) -> Result<()> {
+        let now = time::now();
+        let mut rows = Vec::new();
+        for db in databases.all() {
+            let found = query!(
+                r#"SELECT p.world_id AS "world_id: u32", ek.name, CAST(COUNT(DISTINCT CASE WHEN pd.date >= ? THEN pd.id END) AS UNSIGNED) AS "last_day!: u64", CAST(COUNT(DISTINCT pd.id) AS UNSIGNED) AS "last_week!: u64" FROM environment_killers AS ek INNER JOIN killers AS k ON k.id = ek.kill_id INNER JOIN player_deaths AS pd ON pd.id = k.death_id INNER JOIN players AS p ON p.id = pd.player_id WHERE pd.date >= ? GROUP BY p.world_id, ek.name"#,
+                now.saturating_sub(DAY) as u64,
+                now.saturating_sub(7 * DAY) as u64
+            )
+            .fetch_all(db)
+            .await
+            .context("kill statistics")?;
+            rows.extend(found);
+        }
+
+        let mut merged: HashMap<u32, HashMap<String, Entry>> = HashMap::new();
+        for row in rows {
+            let name = article::strip(row.name.to_lowercase());
+            let entry = merged
+                .entry(row.world_id)
+                .or_default()
+                .entry(name.clone())
+                .or_insert(Entry {
+                    name,
+                    last_day: 0,
+                    last_week: 0,
+                });
+            entry.last_day += row.last_day;
+            entry.last_week += row.last_week;
+        }
+        let summary = merged
+            .into_iter()
+            .map(|(world, entries)| {
+                let mut entries = entries.into_values().collect::<Vec<_>>();
+                entries.sort_by(|a, b| {
+                    b.last_week
+                        .cmp(&a.last_week)
+                        .then(b.last_day.cmp(&a.last_day))
+                        .then(a.name.cmp(&b.name))
+                });
+                (world, entries)
+            })
+            .collect();
+        *self.summary.write().expect("lock") = summary;
+        debug!("Refreshed kill statistics");
+        Ok(())
+    }
+}
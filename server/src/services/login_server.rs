@@ -0,0 +1,320 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::{ensure, Context, Result};
+use num_bigint::BigUint;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{debug, info};
+
+use crate::{
+    config,
+    services::{
+        databases::Databases,
+        repository::{self, Credential},
+    },
+};
+
+const PROTOCOL_LOGIN: u8 = 0x01;
+const RSA_BLOCK: usize = 128;
+/// checksum, protocol, os, version and the dat, spr and pic signatures
+const HEADER: usize = 4 + 1 + 2 + 2 + 12;
+const MAX_PACKET: u16 = 1024;
+
+const ERROR: u8 = 0x0A;
+const MOTD: u8 = 0x14;
+const CHARACTER_LIST: u8 = 0x64;
+
+const DELTA: u32 = 0x9E37_79B9;
+
+struct RsaKey {
+    n: BigUint,
+    d: BigUint,
+}
+
+/// Binds the classic login listener when `login.server.enabled` is set
+pub async fn spawn(databases: &Arc<Databases>) -> Result<()> {
+    let cfg = &config::get().login.server;
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let key = Arc::new(rsa_key(&cfg.rsa_p, &cfg.rsa_q)?);
+    let listener = TcpListener::bind((cfg.address, cfg.port))
+        .await
+        .context("bind")?;
+    info!("Login server listening on {}:{}", cfg.address, cfg.port);
+
+    let databases = databases.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    debug!("Login accept failed: {:?}", err);
+                    continue;
+                }
+            };
+            let databases = databases.clone();
+            let key = key.clone();
+            tokio::spawn(async move {
+                let period = Duration::from_secs(config::get().login.server.timeout);
+                match timeout(period, connection(stream, &databases, &key)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => debug!("Login from {} failed: {:?}", addr, err),
+                    Err(_) => debug!("Login from {} timed out", addr),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn rsa_key(p: &str, q: &str) -> Result<RsaKey> {
+    let p = BigUint::parse_bytes(p.as_bytes(), 10).context("rsa p")?;
+    let q = BigUint::parse_bytes(q.as_bytes(), 10).context("rsa q")?;
+    let one = BigUint::from(1u32);
+    let phi = (&p - &one) * (&q - &one);
+    let d = BigUint::from(65537u32).modinv(&phi).context("rsa primes")?;
+    Ok(RsaKey { n: p * q, d })
+}
+
+async fn connection(mut stream: TcpStream, databases: &Databases, key: &RsaKey) -> Result<()> {
+    let length = stream.read_u16_le().await.context("length")?;
+    ensure!(
+        (HEADER + RSA_BLOCK) as u16 <= length && length <= MAX_PACKET,
+        "packet length {length}"
+    );
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet).await.context("packet")?;
+
+    let checksum = u32::from_le_bytes(packet[0..4].try_into()?);
+    ensure!(checksum == adler32(&packet[4..]), "checksum");
+    ensure!(packet[4] == PROTOCOL_LOGIN, "protocol {:#04x}", packet[4]);
+    let version = u16::from_le_bytes(packet[7..9].try_into()?);
+
+    let block = &packet[HEADER..HEADER + RSA_BLOCK];
+    let decrypted = BigUint::from_bytes_be(block)
+        .modpow(&key.d, &key.n)
+        .to_bytes_be();
+    // Leading zeros are dropped by the conversion, the block starts with one
+    ensure!(decrypted.len() < RSA_BLOCK, "rsa block");
+    let mut plain = vec![0; RSA_BLOCK - decrypted.len()];
+    plain.extend_from_slice(&decrypted);
+    let mut reader = Reader(&plain[1..]);
+    let xtea = [reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?];
+    let account = reader.string()?;
+    let password = reader.string()?;
+
+    let cfg = &config::get().login.server;
+    let response = if cfg.client_version != 0 && version != cfg.client_version {
+        error(&format!(
+            "Only clients with protocol {}.{:02} allowed!",
+            cfg.client_version / 100,
+            cfg.client_version % 100
+        ))
+    } else {
+        characters(databases, &account, &password).await?
+    };
+    stream
+        .write_all(&seal(&response, &xtea))
+        .await
+        .context("response")?;
+    Ok(())
+}
+
+async fn characters(databases: &Databases, account: &str, password: &str) -> Result<Vec<u8>> {
+    let Some(account) =
+        repository::account(databases.accounts(), Credential::Name(account), password).await?
+    else {
+        return Ok(error("Account name or password is not correct."));
+    };
+    let cfg = config::get();
+    let characters = repository::characters(databases, account.id)
+        .await?
+        .into_iter()
+        // IPv6 worlds are rejected by Config::validate
+        .filter_map(|c| {
+            let world = cfg.worlds.get(&c.world_id)?;
+            match world.ip {
+                IpAddr::V4(ip) => Some((c, world, ip)),
+                IpAddr::V6(_) => None,
+            }
+        })
+        .take(u8::MAX as usize)
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    out.push(MOTD);
+    put_string(
+        &mut out,
+        &format!("{}\n{}", cfg.login.server.motd_id, cfg.login.server.motd),
+    );
+    out.push(CHARACTER_LIST);
+    out.push(characters.len() as u8);
+    for (character, world, ip) in characters {
+        put_string(&mut out, &character.name);
+        put_string(&mut out, &world.name);
+        out.extend_from_slice(&ip.octets());
+        out.extend_from_slice(&world.port.to_le_bytes());
+    }
+    out.extend_from_slice(&(account.premdays.clamp(0, u16::MAX as i32) as u16).to_le_bytes());
+    Ok(out)
+}
+
+fn error(message: &str) -> Vec<u8> {
+    let mut out = vec![ERROR];
+    put_string(&mut out, message);
+    out
+}
+
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Length prefixed, XTEA encrypted and checksummed packet
+fn seal(payload: &[u8], key: &[u32; 4]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(payload.len() + 10);
+    body.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    body.extend_from_slice(payload);
+    body.resize(body.len().next_multiple_of(8), 0x33);
+    for block in body.chunks_exact_mut(8) {
+        encrypt(block, key);
+    }
+
+    let mut packet = Vec::with_capacity(body.len() + 6);
+    packet.extend_from_slice(&((body.len() + 4) as u16).to_le_bytes());
+    packet.extend_from_slice(&adler32(&body).to_le_bytes());
+    packet.extend_from_slice(&body);
+    packet
+}
+
+fn encrypt(block: &mut [u8], key: &[u32; 4]) {
+    let mut v0 = u32::from_le_bytes(block[0..4].try_into().expect("block"));
+    let mut v1 = u32::from_le_bytes(block[4..8].try_into().expect("block"));
+    let mut sum = 0u32;
+    for _ in 0..32 {
+        v0 = v0.wrapping_add(
+            ((v1 << 4) ^ (v1 >> 5)).wrapping_add(v1) ^ sum.wrapping_add(key[(sum & 3) as usize]),
+        );
+        sum = sum.wrapping_add(DELTA);
+        v1 = v1.wrapping_add(
+            ((v0 << 4) ^ (v0 >> 5)).wrapping_add(v0)
+                ^ sum.wrapping_add(key[((sum >> 11) & 3) as usize]),
+        );
+    }
+    block[0..4].copy_from_slice(&v0.to_le_bytes());
+    block[4..8].copy_from_slice(&v1.to_le_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        ensure!(self.0.len() >= count, "truncated login packet");
+        let (head, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Primes of the key every Open Tibia client ships the public half of
+    const OT_P: &str = "14299623962416399520070177382898895550795403345466153217470516082934737582776038882967213386204600674145392845853859217990626450972452084065728686565928113";
+    const OT_Q: &str = "7630979195970404721891201847792002125535401292779123937207447574596692788513647179235335529307251350570728407373705564708871762033017096809910315212884101";
+    const OT_N: &str = "109120132967399429278860960508995541528237502902798129123468757937266291492576446330739696001110603907230888610072655818825358503429057592827629436413108566029093628212635953836686562675849720620786279431090218017681061521755056710823876476444260558147179707119674283982419152118103759076030616683978566631413";
+
+    fn block(v0: u32, v1: u32) -> [u8; 8] {
+        let mut block = [0; 8];
+        block[0..4].copy_from_slice(&v0.to_le_bytes());
+        block[4..8].copy_from_slice(&v1.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn xtea_known_answers() {
+        let key = [0x0001_0203, 0x0405_0607, 0x0809_0A0B, 0x0C0D_0E0F];
+        let mut data = block(0x4142_4344, 0x4546_4748);
+        encrypt(&mut data, &key);
+        assert_eq!(data, block(0x497D_F3D0, 0x7261_2CB5));
+
+        let mut data = block(0x4141_4141, 0x4141_4141);
+        encrypt(&mut data, &key);
+        assert_eq!(data, block(0xE78F_2D13, 0x7443_41D8));
+
+        let mut data = block(0x4142_4344, 0x4546_4748);
+        encrypt(&mut data, &[0; 4]);
+        assert_eq!(data, block(0xA039_0589, 0xF8B8_EFA5));
+    }
+
+    #[test]
+    fn adler32_known_answers() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // Longer than one 5552 byte chunk
+        let data = (0..=255u8).cycle().take(256 * 40).collect::<Vec<_>>();
+        assert_eq!(adler32(&data), 0xF475_ED1E);
+    }
+
+    #[test]
+    fn rsa_key_decrypts_client_blocks() {
+        let key = rsa_key(OT_P, OT_Q).expect("key");
+        assert_eq!(key.n.to_string(), OT_N);
+
+        let encrypted = BigUint::parse_bytes(
+            b"6deee571cee50312881746969dfe68d7d9440e6c35307c8f728d8eeaac8e216155c7b3215cefe29024a6cae2d49941d414251120651fb3d0bd9129ad8a2b3007274f027a25b81feac2940a34817715b8e1f772b30e5bb83103faeee7eee347c4215b5df6c56acb2238954af01011856281b32a777c62b62440ebf7dbf5f7ea2e",
+            16,
+        )
+        .expect("block");
+        let plain = encrypted.modpow(&key.d, &key.n).to_bytes_be();
+        assert_eq!(plain, (1..128).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn rsa_key_rejects_invalid_primes() {
+        assert!(rsa_key("", OT_Q).is_err());
+        assert!(rsa_key("abc", OT_Q).is_err());
+    }
+
+    #[test]
+    fn sealed_packets_are_padded_and_checksummed() {
+        let packet = seal(&[1, 2, 3], &[1, 2, 3, 4]);
+        // Length, checksum and one XTEA block holding the payload length and payload
+        assert_eq!(packet.len(), 2 + 4 + 8);
+        assert_eq!(u16::from_le_bytes([packet[0], packet[1]]), 12);
+        let checksum = u32::from_le_bytes(packet[2..6].try_into().expect("checksum"));
+        assert_eq!(checksum, adler32(&packet[6..]));
+    }
+}
@@ -1 +1,12 @@
+pub mod cache;
+pub mod databases;
+pub mod deletion;
+pub mod feed;
+pub mod highscores;
 pub mod jwt;
+pub mod kill_statistics;
+pub mod login_server;
+pub mod online;
+pub mod repository;
+pub mod snapshots;
+pub mod status;
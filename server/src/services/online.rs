@@ -0,0 +1,98 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use sqlx::{query, MySql, Pool, QueryBuilder};
+use tracing::{debug, error};
+
+use crate::{config, services::databases::Databases, utils::time};
+
+pub fn spawn(databases: &Arc<Databases>) {
+    let period = config::get().online.sample_interval;
+    if period == 0 || config::get().worlds.is_empty() {
+        return;
+    }
+    let databases = databases.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(period));
+        loop {
+            interval.tick().await;
+            for db in databases.all() {
+                let worlds = databases.worlds_in(db);
+                if worlds.is_empty() {
+                    continue;
+                }
+                if let Err(err) = sample(db, &worlds).await {
+                    error!("Online sample failed: {:?}", err);
+                }
+            }
+        }
+    });
+}
+
+pub struct Record {
+    pub count: u32,
+    pub time: u64,
+}
+
+/// All-time record of the worlds in the database, as kept by the server in `server_record`.
+/// Counts the earliest time each world reached it
+pub async fn records(db: &Pool<MySql>) -> Result<HashMap<u32, Record>> {
+    Ok(query!(
+        r#"SELECT r.world_id AS "world_id: u32", r.record AS "record: u32", MIN(r.timestamp) AS "timestamp!: u64" FROM server_record AS r INNER JOIN (SELECT world_id, MAX(record) AS record FROM server_record GROUP BY world_id) AS m ON m.world_id = r.world_id AND m.record = r.record GROUP BY r.world_id, r.record"#
+    )
+    .fetch_all(db)
+    .await
+    .context("records")?
+    .into_iter()
+    .map(|r| {
+        (
+            r.world_id,
+            Record {
+                count: r.record,
+                time: r.timestamp,
+            },
+        )
+    })
+    .collect())
+}
+
+async fn sample(db: &Pool<MySql>, worlds: &[u32]) -> Result<()> {
+    let cfg = config::get();
+    let now = time::now();
+    let online: HashMap<_, _> = query!(
+        r#"SELECT world_id AS "world_id: u32", COUNT(*) AS count FROM players WHERE online = 1 AND (? = 0 OR group_id < ?) GROUP BY world_id"#,
+        cfg.staff.group,
+        cfg.staff.group
+    )
+    .fetch_all(db)
+    .await
+    .context("online")?
+    .into_iter()
+    .map(|r| (r.world_id, r.count as u32))
+    .collect();
+
+    // Every configured world gets a sample, empty ones included
+    QueryBuilder::<MySql>::new("INSERT INTO online_history (world_id, time, count) ")
+        .push_values(worlds, |mut row, id| {
+            row.push_bind(id)
+                .push_bind(now as u64)
+                .push_bind(online.get(id).copied().unwrap_or_default());
+        })
+        .build()
+        .execute(db)
+        .await
+        .context("insert online sample")?;
+
+    if cfg.online.history_retention > 0 {
+        // Samples only feed the charts, the record lives in server_record
+        query!(
+            "DELETE FROM online_history WHERE time < ?",
+            now.saturating_sub(cfg.online.history_retention) as u64
+        )
+        .execute(db)
+        .await
+        .context("expire online history")?;
+    }
+    debug!("Recorded online sample for {} worlds", worlds.len());
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use sqlx::{query, query_as, FromRow, MySql, Pool, QueryBuilder};
+
+use crate::{config, utils::article};
+
+use super::databases::Databases;
+
+/// Account name or email, as typed in the client
+pub enum Credential<'a> {
+    Name(&'a str),
+    Email(&'a str),
+}
+
+pub struct Account {
+    pub id: i32,
+    pub name: String,
+    pub premdays: i32,
+}
+
+pub struct Character {
+    pub name: String,
+    pub world_id: u32,
+    pub level: u32,
+    pub vocation: u32,
+    pub sex: u32,
+    pub looktype: u32,
+    pub lookhead: u32,
+    pub lookbody: u32,
+    pub looklegs: u32,
+    pub lookfeet: u32,
+    pub lookaddons: u32,
+}
+
+pub struct OnlinePlayer {
+    pub id: i32,
+    pub name: String,
+    pub level: u32,
+    pub vocation: u32,
+}
+
+#[derive(Default)]
+pub struct DeathFilter {
+    pub world: Option<u32>,
+    /// Date and id of the last death already seen
+    pub cursor: Option<(u64, i32)>,
+    /// Lowest level at death
+    pub min_level: Option<u32>,
+    pub pvp_only: bool,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub character: Option<i32>,
+}
+
+pub struct Death {
+    /// Cursor id, unique together with the date
+    pub id: i32,
+    pub player_id: i32,
+    pub name: String,
+    /// Level at death
+    pub level: u32,
+    pub lost_experience: u64,
+    pub date: u64,
+    pub killers: Vec<Killer>,
+}
+
+pub struct Killer {
+    pub id: Option<i32>,
+    pub name: String,
+}
+
+/// `None` for unknown accounts or a wrong password
+pub async fn account(
+    db: &Pool<MySql>,
+    credential: Credential<'_>,
+    password: &str,
+) -> Result<Option<Account>> {
+    let account = match credential {
+        Credential::Name(name) => query!(
+            "SELECT id, name, premdays FROM accounts WHERE BINARY name=? AND BINARY password=?",
+            name,
+            password
+        )
+        .fetch_optional(db)
+        .await
+        .context("account")?
+        .map(|r| Account {
+            id: r.id,
+            name: r.name,
+            premdays: r.premdays,
+        }),
+        Credential::Email(email) => query!(
+            "SELECT id, name, premdays FROM accounts WHERE email=? AND BINARY password=?",
+            email,
+            password
+        )
+        .fetch_optional(db)
+        .await
+        .context("account")?
+        .map(|r| Account {
+            id: r.id,
+            name: r.name,
+            premdays: r.premdays,
+        }),
+    };
+    Ok(account)
+}
+
+/// Characters the account may log in with across all databases, ordered by name
+pub async fn characters(databases: &Databases, account_id: i32) -> Result<Vec<Character>> {
+    let mut characters = Vec::new();
+    for db in databases.all() {
+        characters.extend(characters_in(db, account_id).await?);
+    }
+    characters.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(characters)
+}
+
+async fn characters_in(db: &Pool<MySql>, account_id: i32) -> Result<Vec<Character>> {
+    query_as!(
+        Character,
+        r#"SELECT name, world_id AS "world_id: u32", level AS "level: u32", vocation AS "vocation: u32", sex AS "sex: u32", looktype AS "looktype: u32", lookhead AS "lookhead: u32", lookbody AS "lookbody: u32", looklegs AS "looklegs: u32", lookfeet AS "lookfeet: u32", lookaddons AS "lookaddons: u32" FROM players WHERE account_id=? AND NOT deleted ORDER BY name"#,
+        account_id
+    )
+    .fetch_all(db)
+    .await
+    .context("characters")
+}
+
+/// Players online on the world, staff excluded, by experience
+pub async fn online(db: &Pool<MySql>, world: u32) -> Result<Vec<OnlinePlayer>> {
+    let staff = config::get().staff.group;
+    query_as!(
+        OnlinePlayer,
+        r#"SELECT id, name, level, vocation FROM players WHERE online = 1 AND (? = 0 OR group_id < ?) AND world_id = ? ORDER BY experience DESC"#,
+        staff,
+        staff,
+        world,
+    )
+    .fetch_all(db)
+    .await
+    .context("online")
+}
+
+/// Number of players online on the world, staff excluded
+pub async fn online_count(db: &Pool<MySql>, world: u32) -> Result<u32> {
+    let staff = config::get().staff.group;
+    let count = query!(
+        r#"SELECT COUNT(*) AS count FROM players WHERE online = 1 AND (? = 0 OR group_id < ?) AND world_id = ?"#,
+        staff,
+        staff,
+        world,
+    )
+    .fetch_one(db)
+    .await
+    .context("online count")?
+    .count;
+    Ok(count as u32)
+}
+
+#[derive(FromRow)]
+struct DeathRow {
+    id: i32,
+    player_id: i32,
+    name: String,
+    level: u32,
+    lost_experience: u64,
+    date: u64,
+}
+
+#[derive(FromRow)]
+struct KillerRow {
+    death_id: i32,
+    id: Option<i32>,
+    name: String,
+}
+
+/// Latest deaths first, with their killers in hit order
+pub async fn deaths(db: &Pool<MySql>, filter: &DeathFilter, count: u32) -> Result<Vec<Death>> {
+    let mut qb = QueryBuilder::new(
+        "SELECT pd.id, p.id AS player_id, p.name, CAST(pd.level AS UNSIGNED) AS level, pd.lost_experience, pd.date FROM player_deaths AS pd INNER JOIN players AS p ON pd.player_id = p.id WHERE 1 = 1",
+    );
+    if let Some(world) = filter.world {
+        qb.push(" AND p.world_id = ").push_bind(world);
+    }
+    if let Some(character) = filter.character {
+        qb.push(" AND p.id = ").push_bind(character);
+    }
+    if let Some(min_level) = filter.min_level {
+        qb.push(" AND pd.level >= ").push_bind(min_level);
+    }
+    if let Some(from) = filter.from {
+        qb.push(" AND pd.date >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        qb.push(" AND pd.date <= ").push_bind(to);
+    }
+    if filter.pvp_only {
+        qb.push(" AND EXISTS (SELECT 1 FROM killers AS k INNER JOIN player_killers AS pk ON pk.kill_id = k.id WHERE k.death_id = pd.id)");
+    }
+    if let Some((date, id)) = filter.cursor {
+        qb.push(" AND (pd.date < ")
+            .push_bind(date)
+            .push(" OR (pd.date = ")
+            .push_bind(date)
+            .push(" AND pd.id < ")
+            .push_bind(id)
+            .push("))");
+    }
+    qb.push(" ORDER BY pd.date DESC, pd.id DESC LIMIT ")
+        .push_bind(count);
+    let deaths = qb
+        .build_query_as::<DeathRow>()
+        .fetch_all(db)
+        .await
+        .context("deaths")?;
+
+    let mut killers: HashMap<i32, Vec<Killer>> = HashMap::new();
+    if !deaths.is_empty() {
+        let mut qb = QueryBuilder::new(
+            "SELECT k.death_id, p.id, COALESCE(p.name, ek.name, '?') AS name FROM killers k LEFT JOIN environment_killers ek ON k.id = ek.kill_id LEFT JOIN player_killers pk ON k.id = pk.kill_id LEFT JOIN players p ON p.id = pk.player_id WHERE k.death_id IN (",
+        );
+        let mut separated = qb.separated(", ");
+        for death in &deaths {
+            separated.push_bind(death.id);
+        }
+        qb.push(") ORDER BY k.death_id, k.final_hit DESC, k.id ASC");
+        for o in qb
+            .build_query_as::<KillerRow>()
+            .fetch_all(db)
+            .await
+            .context("killers")?
+        {
+            let name = if o.id.is_none() {
+                article::strip(o.name)
+            } else {
+                o.name
+            };
+            killers
+                .entry(o.death_id)
+                .or_default()
+                .push(Killer { id: o.id, name });
+        }
+    }
+
+    Ok(deaths
+        .into_iter()
+        .map(|death| Death {
+            killers: killers.remove(&death.id).unwrap_or_default(),
+            id: death.id,
+            player_id: death.player_id,
+            name: death.name,
+            level: death.level,
+            lost_experience: death.lost_experience,
+            date: death.date,
+        })
+        .collect())
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use sqlx::{query, MySql, Pool};
+use tracing::{debug, error};
+
+use crate::{config, utils::time};
+
+pub fn spawn(db: &Pool<MySql>) {
+    let period = config::get().highscores.snapshot_interval;
+    if period == 0 {
+        return;
+    }
+    let db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(period));
+        loop {
+            interval.tick().await;
+            if let Err(err) = snapshot(&db).await {
+                error!("Highscore snapshot failed: {:?}", err);
+            }
+        }
+    });
+}
+
+async fn snapshot(db: &Pool<MySql>) -> Result<()> {
+    let cfg = &config::get().highscores;
+    let now = time::now();
+    let rows = query!(
+        "INSERT INTO player_snapshots (player_id, world_id, time, level, experience, maglevel, manaspent, skill_fist, skill_club, skill_sword, skill_axe, skill_dist, skill_shielding, skill_fishing) SELECT id, world_id, ?, level, experience, maglevel, manaspent, skill_fist, skill_club, skill_sword, skill_axe, skill_dist, skill_shielding, skill_fishing FROM players WHERE (? = 0 OR group_id < ?) AND NOT deleted",
+        now as u64,
+        config::get().staff.group,
+        config::get().staff.group
+    )
+    .execute(db)
+    .await
+    .context("insert snapshots")?
+    .rows_affected();
+
+    if cfg.snapshot_retention > 0 {
+        query!(
+            "DELETE FROM player_snapshots WHERE time < ?",
+            now.saturating_sub(cfg.snapshot_retention) as u64
+        )
+        .execute(db)
+        .await
+        .context("expire snapshots")?;
+    }
+    debug!("Recorded {rows} highscore snapshots");
+    Ok(())
+}
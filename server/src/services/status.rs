@@ -0,0 +1,391 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::config::{StatusProtocol, WorldStatus};
+
+const MAX_RESPONSE: u64 = 64 * 1024;
+
+/// `info` request, answered with a `tsqp` XML document
+const INFO_REQUEST: [u8; 8] = [0x06, 0x00, 0xFF, 0xFF, b'i', b'n', b'f', b'o'];
+
+const BASIC_INFO: u16 = 0x01;
+const MISC_INFO: u16 = 0x04;
+const PLAYERS_INFO: u16 = 0x08;
+const MAP_INFO: u16 = 0x10;
+const SOFTWARE_INFO: u16 = 0x80;
+
+lazy_static! {
+    static ref TAG: Regex = Regex::new(r"<(\w+)([^<>]*?)/?>").expect("tag pattern");
+    static ref ATTRIBUTE: Regex =
+        Regex::new(r#"(\w+)\s*=\s*"([^"]*)""#).expect("attribute pattern");
+    static ref MOTD: Regex = Regex::new(r"(?s)<motd>(.*?)</motd>").expect("motd pattern");
+}
+
+#[derive(Clone, Default)]
+pub struct Status {
+    pub uptime: Option<u64>,
+    pub players_online: Option<u32>,
+    pub players_max: Option<u32>,
+    pub players_peak: Option<u32>,
+    pub motd: Option<String>,
+    pub map: Option<String>,
+    pub server: Option<String>,
+    pub version: Option<String>,
+    pub client_version: Option<String>,
+}
+
+/// Queries a game server over the Open Tibia status protocol
+pub async fn query(ip: IpAddr, cfg: &WorldStatus) -> Result<Status> {
+    let addr = SocketAddr::new(ip, cfg.port);
+    timeout(Duration::from_secs(cfg.timeout), async {
+        let mut stream = TcpStream::connect(addr).await.context("connect")?;
+        match cfg.protocol {
+            StatusProtocol::Xml => xml(&mut stream).await,
+            StatusProtocol::Binary => binary(&mut stream).await,
+        }
+    })
+    .await
+    .context("timeout")?
+}
+
+async fn xml(stream: &mut TcpStream) -> Result<Status> {
+    stream.write_all(&INFO_REQUEST).await.context("send")?;
+    let mut body = Vec::new();
+    stream
+        .take(MAX_RESPONSE)
+        .read_to_end(&mut body)
+        .await
+        .context("receive")?;
+    parse_xml(&String::from_utf8_lossy(&body))
+}
+
+fn parse_xml(body: &str) -> Result<Status> {
+    if !body.contains("<tsqp") {
+        bail!("not a status document");
+    }
+    let tags: HashMap<_, HashMap<_, _>> = TAG
+        .captures_iter(body)
+        .map(|tag| {
+            let attributes = ATTRIBUTE
+                .captures_iter(tag.get(2).map_or("", |a| a.as_str()))
+                .map(|a| (a[1].to_owned(), unescape(&a[2])))
+                .collect();
+            (tag[1].to_owned(), attributes)
+        })
+        .collect();
+    let attribute = |tag: &str, name: &str| tags.get(tag).and_then(|a| a.get(name)).cloned();
+    let number = |tag: &str, name: &str| attribute(tag, name).and_then(|v| v.parse().ok());
+
+    Ok(Status {
+        uptime: attribute("serverinfo", "uptime").and_then(|v| v.parse().ok()),
+        players_online: number("players", "online"),
+        players_max: number("players", "max"),
+        players_peak: number("players", "peak"),
+        motd: MOTD
+            .captures(body)
+            .map(|m| unescape(m[1].trim()))
+            .filter(|m| !m.is_empty()),
+        map: attribute("map", "name"),
+        server: attribute("serverinfo", "server"),
+        version: attribute("serverinfo", "version"),
+        client_version: attribute("serverinfo", "client"),
+    })
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+async fn binary(stream: &mut TcpStream) -> Result<Status> {
+    let info = BASIC_INFO | MISC_INFO | PLAYERS_INFO | MAP_INFO | SOFTWARE_INFO;
+    let mut request = vec![0x04, 0x00, 0xFF, 0x01];
+    request.extend_from_slice(&info.to_le_bytes());
+    stream.write_all(&request).await.context("send")?;
+
+    let length = stream.read_u16_le().await.context("receive length")?;
+    let mut body = vec![0; length as usize];
+    stream.read_exact(&mut body).await.context("receive")?;
+    parse_binary(&body)
+}
+
+fn parse_binary(body: &[u8]) -> Result<Status> {
+    let mut status = Status::default();
+    let mut reader = Reader(body);
+    while !reader.0.is_empty() {
+        match reader.u8()? {
+            // Basic: name, ip, port
+            0x10 => {
+                reader.string()?;
+                reader.string()?;
+                reader.string()?;
+            }
+            // Owner: name, email
+            0x11 => {
+                reader.string()?;
+                reader.string()?;
+            }
+            // Misc: motd, location, url, uptime
+            0x12 => {
+                status.motd = Some(reader.string()?).filter(|m| !m.is_empty());
+                reader.string()?;
+                reader.string()?;
+                status.uptime = Some(reader.u64()?);
+            }
+            0x20 => {
+                status.players_online = Some(reader.u32()?);
+                status.players_max = Some(reader.u32()?);
+                status.players_peak = Some(reader.u32()?);
+            }
+            // Extended players: name and level each
+            0x21 => {
+                for _ in 0..reader.u32()? {
+                    reader.string()?;
+                    reader.u32()?;
+                }
+            }
+            0x22 => {
+                reader.u8()?;
+            }
+            // Map: name, author, width, height
+            0x30 => {
+                status.map = Some(reader.string()?);
+                reader.string()?;
+                reader.take(4)?;
+            }
+            0x23 => {
+                status.server = Some(reader.string()?);
+                status.version = Some(reader.string()?);
+                status.client_version = Some(reader.string()?);
+            }
+            section => bail!("unknown status section {section:#04x}"),
+        }
+    }
+    Ok(status)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.0.len() < count {
+            bail!("truncated status response");
+        }
+        let (head, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const XML: &str = r#"<?xml version="1.0"?>
+<tsqp version="1.0">
+    <serverinfo uptime="3600" ip="127.0.0.1" servername="Forgotten" port="7171" server="The Forgotten Server" version="0.4" client="8.60"/>
+    <owner name="" email=""/>
+    <players online="12" max="1000" peak="40"/>
+    <monsters total="9000"/>
+    <map name="forgotten" author="Talaturen" width="2048" height="2048"/>
+    <motd>Welcome to &lt;Forgotten&gt; &amp; co.</motd>
+</tsqp>"#;
+
+    fn string(body: &mut Vec<u8>, value: &str) {
+        body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        body.extend_from_slice(value.as_bytes());
+    }
+
+    fn binary_body() -> Vec<u8> {
+        let mut body = vec![0x10];
+        string(&mut body, "Forgotten");
+        string(&mut body, "127.0.0.1");
+        string(&mut body, "7171");
+        body.push(0x12);
+        string(&mut body, "Welcome");
+        string(&mut body, "Europe");
+        string(&mut body, "https://example.org");
+        body.extend_from_slice(&3600u64.to_le_bytes());
+        body.push(0x20);
+        for count in [12u32, 1000, 40] {
+            body.extend_from_slice(&count.to_le_bytes());
+        }
+        body.push(0x30);
+        string(&mut body, "forgotten");
+        string(&mut body, "Talaturen");
+        body.extend_from_slice(&[0x00, 0x08, 0x00, 0x08]);
+        body.push(0x23);
+        string(&mut body, "The Forgotten Server");
+        string(&mut body, "1.4");
+        string(&mut body, "10.98");
+        body
+    }
+
+    /// Serves one connection: checks the request, answers and closes
+    async fn serve(request: Vec<u8>, response: Vec<u8>) -> u16 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("bind");
+        let port = listener.local_addr().expect("address").port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("accept");
+            let mut received = vec![0; request.len()];
+            stream.read_exact(&mut received).await.expect("request");
+            assert_eq!(received, request);
+            stream.write_all(&response).await.expect("response");
+        });
+        port
+    }
+
+    fn config(port: u16, protocol: StatusProtocol) -> WorldStatus {
+        WorldStatus {
+            enabled: true,
+            port,
+            protocol,
+            timeout: 1,
+        }
+    }
+
+    #[test]
+    fn parses_xml_document() {
+        let status = parse_xml(XML).expect("status");
+        assert_eq!(status.uptime, Some(3600));
+        assert_eq!(status.players_online, Some(12));
+        assert_eq!(status.players_max, Some(1000));
+        assert_eq!(status.players_peak, Some(40));
+        assert_eq!(status.motd.as_deref(), Some("Welcome to <Forgotten> & co."));
+        assert_eq!(status.map.as_deref(), Some("forgotten"));
+        assert_eq!(status.server.as_deref(), Some("The Forgotten Server"));
+        assert_eq!(status.version.as_deref(), Some("0.4"));
+        assert_eq!(status.client_version.as_deref(), Some("8.60"));
+    }
+
+    #[test]
+    fn missing_xml_attributes_are_none() {
+        let status =
+            parse_xml(r#"<tsqp><players online="3"/><motd></motd></tsqp>"#).expect("status");
+        assert_eq!(status.players_online, Some(3));
+        assert_eq!(status.players_max, None);
+        assert_eq!(status.uptime, None);
+        assert_eq!(status.motd, None);
+        assert_eq!(status.map, None);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(parse_xml("<html></html>").is_err());
+    }
+
+    #[test]
+    fn parses_binary_sections() {
+        let status = parse_binary(&binary_body()).expect("status");
+        assert_eq!(status.uptime, Some(3600));
+        assert_eq!(status.players_online, Some(12));
+        assert_eq!(status.players_max, Some(1000));
+        assert_eq!(status.players_peak, Some(40));
+        assert_eq!(status.motd.as_deref(), Some("Welcome"));
+        assert_eq!(status.map.as_deref(), Some("forgotten"));
+        assert_eq!(status.server.as_deref(), Some("The Forgotten Server"));
+        assert_eq!(status.version.as_deref(), Some("1.4"));
+        assert_eq!(status.client_version.as_deref(), Some("10.98"));
+    }
+
+    #[test]
+    fn rejects_truncated_binary_section() {
+        let body = binary_body();
+        assert!(parse_binary(&body[..body.len() - 3]).is_err());
+        assert!(parse_binary(&[0x20, 0x0C, 0x00]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_binary_section() {
+        assert!(parse_binary(&[0x99]).is_err());
+    }
+
+    #[tokio::test]
+    async fn queries_xml_server() {
+        let port = serve(INFO_REQUEST.to_vec(), XML.as_bytes().to_vec()).await;
+        let status = query(
+            Ipv4Addr::LOCALHOST.into(),
+            &config(port, StatusProtocol::Xml),
+        )
+        .await
+        .expect("status");
+        assert_eq!(status.players_online, Some(12));
+        assert_eq!(status.version.as_deref(), Some("0.4"));
+    }
+
+    #[tokio::test]
+    async fn queries_binary_server() {
+        let body = binary_body();
+        let mut response = (body.len() as u16).to_le_bytes().to_vec();
+        response.extend_from_slice(&body);
+        let port = serve(vec![0x04, 0x00, 0xFF, 0x01, 0x9D, 0x00], response).await;
+        let status = query(
+            Ipv4Addr::LOCALHOST.into(),
+            &config(port, StatusProtocol::Binary),
+        )
+        .await
+        .expect("status");
+        assert_eq!(status.players_online, Some(12));
+        assert_eq!(status.version.as_deref(), Some("1.4"));
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("bind");
+        let port = listener.local_addr().expect("address").port();
+        let err = query(
+            Ipv4Addr::LOCALHOST.into(),
+            &config(port, StatusProtocol::Xml),
+        )
+        .await
+        .err()
+        .expect("timeout");
+        assert_eq!(err.to_string(), "timeout");
+        drop(listener);
+    }
+}
@@ -0,0 +1,9 @@
+pub fn strip(name: String) -> String {
+    if let Some(stripped) = name.strip_prefix("a ") {
+        stripped.to_owned()
+    } else if let Some(stripped) = name.strip_prefix("an ") {
+        stripped.to_owned()
+    } else {
+        name
+    }
+}
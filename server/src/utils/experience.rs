@@ -0,0 +1,29 @@
+/// Experience needed for the level, `None` when it does not fit in a u64
+pub fn for_level(level: u32) -> Option<u64> {
+    let l = level.saturating_sub(1) as u64;
+    let cube = l.checked_mul(l)?.checked_mul(l)?.checked_mul(50)?;
+    let linear = l.checked_mul(400)?;
+    let square = l.checked_mul(l)?.checked_mul(150)?;
+    Some(cube.checked_add(linear)?.checked_sub(square)? / 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_experience_table() {
+        assert_eq!(for_level(0), Some(0));
+        assert_eq!(for_level(1), Some(0));
+        assert_eq!(for_level(2), Some(100));
+        assert_eq!(for_level(8), Some(4200));
+        assert_eq!(for_level(100), Some(15_694_800));
+        assert_eq!(for_level(500), Some(2_058_474_800));
+        assert_eq!(for_level(1000), Some(16_566_949_800));
+    }
+
+    #[test]
+    fn overflow_is_none() {
+        assert_eq!(for_level(u32::MAX), None);
+    }
+}
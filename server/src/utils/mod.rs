@@ -1 +1,3 @@
+pub mod article;
+pub mod experience;
 pub mod time;
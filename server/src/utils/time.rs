@@ -6,3 +6,8 @@ pub fn now() -> usize {
         .expect("time")
         .as_secs() as usize
 }
+
+/// Current unix time as stored in the database
+pub fn timestamp() -> u64 {
+    now() as u64
+}